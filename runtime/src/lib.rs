@@ -0,0 +1,58 @@
+//! Support functions called by code generated with
+//! [`subtle-derive`](https://docs.rs/subtle-derive).
+//!
+//! A proc macro crate can only export macros, so the out-of-line byte comparisons shared by every
+//! type marked `#[ct(code_size)]` live here instead. Code generated by `subtle-derive` only refers
+//! to this crate for such types, which require its `runtime` feature, in which case this crate
+//! must also be a dependency of the crate using the derives.
+
+#![no_std]
+#![warn(missing_docs)]
+#![deny(rustdoc::missing_crate_level_docs)]
+/* Make all doctests fail if they produce any warnings. */
+#![doc(test(attr(deny(warnings))))]
+#![deny(clippy::all)]
+
+use subtle::{Choice, ConstantTimeEq, ConstantTimeGreater};
+
+/// Compare two byte slices of the same length for equality, out of line.
+///
+/// Unlike the generic `ConstantTimeEq` impl for slices, this is compiled only once, so every
+/// comparison of byte arrays with `#[ct(code_size)]` shares the same code.
+///
+///```
+/// use subtle_derive_runtime::bytes_ct_eq;
+///
+/// assert_eq!(1, bytes_ct_eq(&[1, 2, 3], &[1, 2, 3]).unwrap_u8());
+/// assert_eq!(0, bytes_ct_eq(&[1, 2, 3], &[1, 2, 4]).unwrap_u8());
+///```
+#[inline(never)]
+pub fn bytes_ct_eq(a: &[u8], b: &[u8]) -> Choice { ConstantTimeEq::ct_eq(a, b) }
+
+/// Compare two byte slices of the same length lexicographically, out of line.
+///
+/// Returns whether `a` is greater than `b`, and whether `a` is less than `b`. Both are false
+/// exactly when the slices are equal.
+///
+///```
+/// use subtle_derive_runtime::bytes_ct_cmp;
+///
+/// let (gt, lt) = bytes_ct_cmp(&[1, 2, 3], &[1, 1, 9]);
+/// assert_eq!((1, 0), (gt.unwrap_u8(), lt.unwrap_u8()));
+/// let (gt, lt) = bytes_ct_cmp(&[1, 2, 3], &[1, 2, 3]);
+/// assert_eq!((0, 0), (gt.unwrap_u8(), lt.unwrap_u8()));
+/// let (gt, lt) = bytes_ct_cmp(&[0, 9, 9], &[1, 0, 0]);
+/// assert_eq!((0, 1), (gt.unwrap_u8(), lt.unwrap_u8()));
+///```
+#[inline(never)]
+pub fn bytes_ct_cmp(a: &[u8], b: &[u8]) -> (Choice, Choice) {
+  let mut gt = Choice::from(0);
+  let mut lt = Choice::from(0);
+  let mut eq = Choice::from(1);
+  for (x, y) in a.iter().zip(b) {
+    gt |= eq & x.ct_gt(y);
+    lt |= eq & y.ct_gt(x);
+    eq &= x.ct_eq(y);
+  }
+  (gt, lt)
+}
@@ -0,0 +1,341 @@
+//! Syntactic tracking of secret values within function bodies, shared by the attribute macros.
+//!
+//! Without type information, a proc macro can only approximate which values are secret. This
+//! module treats a variable as secret if it is a function argument marked `#[secret]`, or if it is
+//! bound by a `let` whose initializer refers to a secret variable. A `let` with any other
+//! initializer shadows a secret variable of the same name with a public one, until the end of the
+//! block it's in. Values which are syntactically
+//! known to be a `Choice` (e.g. the result of `ct_eq()`) are always considered secret. The
+//! lengths of secret values (from `len()` or `is_empty()`) are considered public, as in `subtle`.
+
+use crate::codes::Code;
+use std::collections::BTreeSet;
+use syn::{
+  ext::IdentExt,
+  punctuated::Punctuated,
+  visit::{self, Visit},
+  *,
+};
+
+/* Method names on the subtle traits which produce a `Choice`. */
+const CHOICE_METHODS: &[&str] = &["ct_eq", "ct_ne", "ct_gt", "ct_lt"];
+
+/* Method names which produce public information about a secret value. */
+const PUBLIC_METHODS: &[&str] = &["len", "is_empty"];
+
+/* Macros from this crate which evaluate to a `Choice`. */
+const CHOICE_MACROS: &[&str] = &["ct_validate", "ct_eq_chain"];
+
+/* Whether `ty` is (a reference to) a path ending in `Choice`. */
+pub(crate) fn is_choice_type(ty: &Type) -> bool {
+  match ty {
+    Type::Path(TypePath { path, .. }) => path
+      .segments
+      .last()
+      .map_or(false, |segment| segment.ident == "Choice"),
+    Type::Reference(TypeReference { elem, .. })
+    | Type::Paren(TypeParen { elem, .. })
+    | Type::Group(TypeGroup { elem, .. }) => is_choice_type(elem),
+    _ => false,
+  }
+}
+
+/* Collect every identifier bound by a pattern. */
+pub(crate) fn pat_idents(pat: &Pat) -> Vec<Ident> {
+  struct Idents(Vec<Ident>);
+
+  impl<'ast> Visit<'ast> for Idents {
+    fn visit_pat_ident(&mut self, pat: &'ast PatIdent) {
+      self.0.push(pat.ident.clone());
+      visit::visit_pat_ident(self, pat);
+    }
+  }
+
+  let mut idents = Idents(Vec::new());
+  idents.visit_pat(pat);
+  idents.0
+}
+
+/* Remove every marker attribute `#[name]` from `attrs`, returning whether there were any. */
+pub(crate) fn take_marker(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+  let before = attrs.len();
+  attrs.retain(|attr| !attr.path.is_ident(name));
+  attrs.len() != before
+}
+
+/* The attribute macros which accept `#[secret]` arguments, and so may follow
+ * `#[ct_public_inputs(...)]`. */
+pub(crate) const CHECKING_ATTRS: &[&str] = &[
+  "ct_branchless",
+  "deny_secret_branches",
+  "ct_loop_bound",
+  "ct_equalize",
+];
+
+/* Parse the argument names from the arguments of `#[ct_public_inputs(...)]`. */
+pub(crate) fn parse_public_inputs(input: parse::ParseStream) -> Result<Vec<Ident>> {
+  let names = Punctuated::<Ident, Token![,]>::parse_terminated_with(input, Ident::parse_any)?;
+  Ok(names.into_iter().collect())
+}
+
+/* Remove any `#[ct_public_inputs(...)]` attribute from `attrs`, returning the names it lists. */
+pub(crate) fn take_public_inputs(attrs: &mut Vec<Attribute>) -> Result<Option<Vec<Ident>>> {
+  let mut ret: Option<Vec<Ident>> = None;
+  for attr in attrs.iter() {
+    if attr.path.is_ident("ct_public_inputs") {
+      let names = attr.parse_args_with(parse_public_inputs)?;
+      ret.get_or_insert_with(Vec::new).extend(names);
+    }
+  }
+  take_marker(attrs, "ct_public_inputs");
+  Ok(ret)
+}
+
+/* Mark every argument of `sig` not named in `public` as `#[secret]`. */
+pub(crate) fn mark_secret_inputs(sig: &mut Signature, public: &[Ident]) -> Result<()> {
+  let mut names = BTreeSet::new();
+  for input in sig.inputs.iter_mut() {
+    match input {
+      FnArg::Receiver(_) => {
+        names.insert("self".to_string());
+      },
+      FnArg::Typed(PatType { attrs, pat, .. }) => {
+        let idents = pat_idents(pat);
+        let is_public = idents.iter().any(|ident| public.contains(ident));
+        names.extend(idents.iter().map(ToString::to_string));
+        if !is_public && !attrs.iter().any(|attr| attr.path.is_ident("secret")) {
+          attrs.push(parse_quote!(#[secret]));
+        }
+      },
+    }
+  }
+  match public
+    .iter()
+    .find(|ident| !names.contains(&ident.to_string()))
+  {
+    Some(ident) => Err(Code::UnknownPublicInput.error(ident, "no argument with this name")),
+    None => Ok(()),
+  }
+}
+
+/// The set of variables within a function body which are considered secret. These are ordered
+/// sets, so that nothing derived from them can depend on hash iteration order.
+#[derive(Clone, Default)]
+pub(crate) struct Taint {
+  /// Variables holding secret data.
+  secrets: BTreeSet<String>,
+  /// Variables known to hold a `Choice`, which are also secret.
+  choices: BTreeSet<String>,
+}
+
+impl Taint {
+  /* Mark arguments annotated with `#[secret]` as secret, and arguments of type `Choice` as
+   * choices. The `#[secret]` annotations are removed, since they are not real attributes. */
+  pub(crate) fn from_inputs(sig: &mut Signature) -> Self {
+    let mut ret = Self::default();
+    for input in sig.inputs.iter_mut() {
+      if let FnArg::Typed(PatType { attrs, pat, ty, .. }) = input {
+        let is_secret = take_marker(attrs, "secret");
+        for ident in pat_idents(pat) {
+          if is_choice_type(ty) {
+            ret.choices.insert(ident.to_string());
+          } else if is_secret {
+            ret.secrets.insert(ident.to_string());
+          }
+        }
+      }
+    }
+    ret
+  }
+
+  /* As `from_inputs()`, but if the function has a `#[ct_public_inputs(...)]` attribute, every
+   * argument it doesn't list is secret. */
+  pub(crate) fn from_fn(item: &mut ItemFn) -> Result<Self> {
+    if let Some(public) = take_public_inputs(&mut item.attrs)? {
+      mark_secret_inputs(&mut item.sig, &public)?;
+    }
+    Ok(Self::from_inputs(&mut item.sig))
+  }
+
+  /* Mark the receiver and every argument not named in `public` as secret, for methods of a
+   * constant-time trait impl where all operands are secret by default. Arguments of type `Choice`
+   * are marked as choices. */
+  pub(crate) fn from_all_inputs(sig: &Signature, public: &[Ident]) -> Self {
+    let mut ret = Self::default();
+    for input in sig.inputs.iter() {
+      match input {
+        FnArg::Receiver(_) => {
+          if !public.iter().any(|ident| *ident == "self") {
+            ret.secrets.insert("self".to_string());
+          }
+        },
+        FnArg::Typed(PatType { pat, ty, .. }) => {
+          for ident in pat_idents(pat) {
+            if is_choice_type(ty) {
+              ret.choices.insert(ident.to_string());
+            } else if !public.contains(&ident) {
+              ret.secrets.insert(ident.to_string());
+            }
+          }
+        },
+      }
+    }
+    ret
+  }
+
+  /* Whether `expr` is syntactically known to evaluate to a `Choice`. */
+  pub(crate) fn is_choice(&self, expr: &Expr) -> bool {
+    match expr {
+      Expr::Path(ExprPath { path, .. }) => path
+        .get_ident()
+        .map_or(false, |ident| self.choices.contains(&ident.to_string())),
+      Expr::MethodCall(ExprMethodCall { method, .. }) => {
+        CHOICE_METHODS.iter().any(|name| method == name)
+      },
+      Expr::Call(ExprCall { func, .. }) => match &**func {
+        /* Either a UFCS call like `ConstantTimeEq::ct_eq(a, b)`, or `Choice::from(x)`. */
+        Expr::Path(ExprPath { path, .. }) => {
+          let mut segments = path.segments.iter().rev();
+          match (segments.next(), segments.next()) {
+            (Some(last), _) if CHOICE_METHODS.iter().any(|name| last.ident == name) => true,
+            (Some(last), Some(ty)) => last.ident == "from" && ty.ident == "Choice",
+            _ => false,
+          }
+        },
+        _ => false,
+      },
+      Expr::Unary(ExprUnary {
+        op: UnOp::Not(_),
+        expr,
+        ..
+      }) => self.is_choice(expr),
+      Expr::Binary(ExprBinary {
+        left,
+        op: BinOp::BitAnd(_) | BinOp::BitOr(_) | BinOp::BitXor(_),
+        right,
+        ..
+      }) => self.is_choice(left) || self.is_choice(right),
+      Expr::Paren(ExprParen { expr, .. }) | Expr::Group(ExprGroup { expr, .. }) => {
+        self.is_choice(expr)
+      },
+      Expr::Macro(ExprMacro { mac, .. }) => mac.path.segments.last().map_or(false, |segment| {
+        CHOICE_MACROS.iter().any(|name| segment.ident == name)
+      }),
+      _ => false,
+    }
+  }
+
+  /* Whether `expr` refers to any secret variable or produces a `Choice`. */
+  pub(crate) fn mentions_secret(&self, expr: &Expr) -> bool {
+    struct Finder<'a> {
+      taint: &'a Taint,
+      found: bool,
+    }
+
+    impl<'a, 'ast> Visit<'ast> for Finder<'a> {
+      fn visit_expr(&mut self, expr: &'ast Expr) {
+        if self.taint.is_choice(expr) {
+          self.found = true;
+        }
+        visit::visit_expr(self, expr);
+      }
+
+      fn visit_expr_method_call(&mut self, expr: &'ast ExprMethodCall) {
+        if !PUBLIC_METHODS.iter().any(|name| expr.method == name) {
+          visit::visit_expr_method_call(self, expr);
+        }
+      }
+
+      fn visit_expr_path(&mut self, expr: &'ast ExprPath) {
+        if let Some(ident) = expr.path.get_ident() {
+          let name = ident.to_string();
+          if self.taint.secrets.contains(&name) || self.taint.choices.contains(&name) {
+            self.found = true;
+          }
+        }
+      }
+    }
+
+    let mut finder = Finder {
+      taint: self,
+      found: false,
+    };
+    finder.visit_expr(expr);
+    finder.found
+  }
+
+  /* Record the variables bound by a `let`, which are secret if their initializer is. */
+  pub(crate) fn bind_local(&mut self, local: &Local) {
+    let (pat, ty) = match &local.pat {
+      Pat::Type(PatType { pat, ty, .. }) => (&**pat, Some(&**ty)),
+      pat => (pat, None),
+    };
+    let init = local.init.as_ref().map(|(_, init)| &**init);
+    let is_choice =
+      ty.map_or(false, is_choice_type) || init.map_or(false, |init| self.is_choice(init));
+    let is_secret = init.map_or(false, |init| self.mentions_secret(init));
+    self.bind_pat(pat, is_choice, is_secret);
+  }
+
+  /* Record the variables bound by a `for` loop, which are secret if the iterator is. */
+  pub(crate) fn bind_for_loop(&mut self, expr: &ExprForLoop) {
+    let is_secret = self.mentions_secret(&expr.expr);
+    self.bind_pat(&expr.pat, false, is_secret);
+  }
+
+  /* Each binding shadows any earlier variable of the same name, so it is only secret if its own
+   * value is. */
+  fn bind_pat(&mut self, pat: &Pat, is_choice: bool, is_secret: bool) {
+    for ident in pat_idents(pat) {
+      let name = ident.to_string();
+      self.choices.remove(&name);
+      self.secrets.remove(&name);
+      if is_choice {
+        self.choices.insert(name);
+      } else if is_secret {
+        self.secrets.insert(name);
+      }
+    }
+  }
+}
+
+/* Find the first `return`, `?`, or `break`/`continue` which would leave `expr` early. Closures and
+ * nested items are not searched, since control flow can't escape them. */
+pub(crate) fn find_escape(expr: &Expr) -> Option<proc_macro2::TokenStream> {
+  struct Escapes {
+    loop_depth: usize,
+    found: Option<proc_macro2::TokenStream>,
+  }
+
+  impl<'ast> Visit<'ast> for Escapes {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+      if self.found.is_some() {
+        return;
+      }
+      match expr {
+        Expr::Return(_) | Expr::Try(_) => {
+          self.found = Some(quote::quote!(#expr));
+        },
+        Expr::Break(_) | Expr::Continue(_) if self.loop_depth == 0 => {
+          self.found = Some(quote::quote!(#expr));
+        },
+        Expr::Loop(_) | Expr::While(_) | Expr::ForLoop(_) => {
+          self.loop_depth += 1;
+          visit::visit_expr(self, expr);
+          self.loop_depth -= 1;
+        },
+        Expr::Closure(_) | Expr::Async(_) => (),
+        _ => visit::visit_expr(self, expr),
+      }
+    }
+
+    fn visit_item(&mut self, _item: &'ast Item) {}
+  }
+
+  let mut escapes = Escapes {
+    loop_depth: 0,
+    found: None,
+  };
+  escapes.visit_expr(expr);
+  escapes.found
+}
@@ -0,0 +1,137 @@
+//! Rewriting of `if`/`else` on `Choice` conditions into `conditional_select()`, for
+//! `#[ct_branchless]`.
+
+use crate::{
+  analysis::{find_escape, Taint},
+  codes::Code,
+};
+use quote::{quote, ToTokens};
+use syn::{
+  visit_mut::{self, VisitMut},
+  *,
+};
+
+struct Branchless {
+  taint: Taint,
+  errors: Vec<Error>,
+}
+
+impl Branchless {
+  fn error(&mut self, code: Code, tokens: impl ToTokens, message: &str) {
+    self.errors.push(code.error(tokens, message));
+  }
+
+  /* Check that neither arm of a rewritten `if` could leave the function early, since both arms
+   * are now evaluated unconditionally. */
+  fn check_arms(&mut self, then_branch: &Block, else_branch: &Expr) {
+    let then_expr = Expr::Block(ExprBlock {
+      attrs: Vec::new(),
+      label: None,
+      block: then_branch.clone(),
+    });
+    for arm in [&then_expr, else_branch] {
+      if let Some(escape) = find_escape(arm) {
+        self.error(
+          Code::EarlyExit,
+          escape,
+          "cannot leave a constant-time `if` early, since both of its arms are always evaluated",
+        );
+      }
+    }
+  }
+
+  /* Produce the branch-free replacement for an `if` expression, or record why it is invalid. */
+  fn rewrite(&mut self, expr: &Expr) -> Option<Expr> {
+    match expr {
+      Expr::If(ExprIf {
+        cond,
+        then_branch,
+        else_branch,
+        ..
+      }) if self.taint.is_choice(cond) => match else_branch {
+        Some((_, else_expr)) => {
+          self.check_arms(then_branch, else_expr);
+          Some(parse_quote! {
+            ::subtle::ConditionallySelectable::conditional_select(
+              &#else_expr,
+              &#then_branch,
+              #cond,
+            )
+          })
+        },
+        None => {
+          self.error(
+            Code::MissingElse,
+            expr,
+            "a constant-time `if` needs an `else` branch, since both arms are always evaluated",
+          );
+          None
+        },
+      },
+      Expr::If(ExprIf { cond, .. }) if self.taint.mentions_secret(cond) => {
+        self.error(
+          Code::SecretBranch,
+          cond,
+          "cannot branch on a secret value; compute a `Choice` with e.g. `ct_eq()` instead",
+        );
+        None
+      },
+      Expr::Match(ExprMatch {
+        expr: scrutinee, ..
+      }) if self.taint.mentions_secret(scrutinee) => {
+        self.error(
+          Code::SecretMatch,
+          scrutinee,
+          "cannot `match` on a secret value",
+        );
+        None
+      },
+      _ => None,
+    }
+  }
+}
+
+impl VisitMut for Branchless {
+  fn visit_local_mut(&mut self, local: &mut Local) {
+    visit_mut::visit_local_mut(self, local);
+    self.taint.bind_local(local);
+  }
+
+  /* Bindings within a block go out of scope at its end, along with any shadowing they did. */
+  fn visit_block_mut(&mut self, block: &mut Block) {
+    let outer = self.taint.clone();
+    visit_mut::visit_block_mut(self, block);
+    self.taint = outer;
+  }
+
+  fn visit_expr_for_loop_mut(&mut self, expr: &mut ExprForLoop) {
+    self.taint.bind_for_loop(expr);
+    visit_mut::visit_expr_for_loop_mut(self, expr);
+  }
+
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    /* Rewrite nested expressions first, so that `else if` chains become nested selections. */
+    visit_mut::visit_expr_mut(self, expr);
+    if let Some(replacement) = self.rewrite(expr) {
+      *expr = replacement;
+    }
+  }
+
+  /* Nested functions have their own arguments, and aren't rewritten. */
+  fn visit_item_mut(&mut self, _item: &mut Item) {}
+}
+
+/* Rewrite the body of `item`, returning any errors as compile errors. */
+pub(crate) fn rewrite_fn(item: &mut ItemFn) -> proc_macro2::TokenStream {
+  let taint = match Taint::from_fn(item) {
+    Ok(taint) => taint,
+    Err(error) => return error.to_compile_error(),
+  };
+  let mut branchless = Branchless {
+    taint,
+    errors: Vec::new(),
+  };
+  branchless.visit_block_mut(&mut item.block);
+  let errors = branchless.errors.iter().map(Error::to_compile_error);
+  quote! { #(#errors)* }
+}
@@ -0,0 +1,290 @@
+//! Detection of variable-time operations on secret values, for the checking attribute macros.
+
+use crate::{
+  analysis::{find_escape, take_public_inputs, Taint},
+  codes::Code,
+};
+use quote::{quote, ToTokens};
+use syn::{
+  visit::{self, Visit},
+  *,
+};
+
+/// The set of operations rejected by a checking attribute.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rules {
+  /// `#[constant_time_impl]`: comparisons, short-circuiting, branches, and indexing.
+  Impl,
+  /// `#[deny_secret_branches]`: early exits from secret branches, `?`, and indexing.
+  Branches,
+  /// `#[ct_loop_bound]`: loops whose trip count depends on a secret.
+  Loops,
+}
+
+struct Checker {
+  rules: Rules,
+  taint: Taint,
+  errors: Vec<Error>,
+}
+
+impl Checker {
+  fn error(&mut self, code: Code, tokens: impl ToTokens, message: &str) {
+    self.errors.push(code.error(tokens, message));
+  }
+
+  /* Record an error if control flow could leave `arm` early, when whether `arm` runs depends on
+   * a secret. */
+  fn check_arm(&mut self, arm: &Expr) {
+    if let Some(escape) = find_escape(arm) {
+      let (code, message) = match self.rules {
+        Rules::Loops => (
+          Code::SecretLoop,
+          "cannot exit a loop early from a branch taken on a secret value, since the loop's trip \
+           count would depend on the secret",
+        ),
+        _ => (
+          Code::EarlyExit,
+          "cannot exit early from a branch taken on a secret value; compute the result for both \
+           branches and use `conditional_select()` instead",
+        ),
+      };
+      self.error(code, escape, message);
+    }
+  }
+
+  /* Check each arm of an `if` on a secret condition. */
+  fn check_if_arms(
+    &mut self,
+    then_branch: &Block,
+    else_branch: &Option<(Token![else], Box<Expr>)>,
+  ) {
+    self.check_arm(&Expr::Block(ExprBlock {
+      attrs: Vec::new(),
+      label: None,
+      block: then_branch.clone(),
+    }));
+    if let Some((_, else_expr)) = else_branch {
+      self.check_arm(else_expr);
+    }
+  }
+
+  /* Record an error if `expr` is a variable-time operation on a secret value. */
+  fn check_impl(&mut self, expr: &Expr) {
+    match expr {
+      Expr::Binary(ExprBinary { op, .. }) => {
+        let (code, message) = match op {
+          BinOp::Eq(_) | BinOp::Ne(_) => (
+            Code::SecretComparison,
+            "cannot compare secret values with `==` or `!=`; use `ct_eq()` instead",
+          ),
+          BinOp::Lt(_) | BinOp::Le(_) | BinOp::Gt(_) | BinOp::Ge(_) => (
+            Code::SecretComparison,
+            "cannot compare secret values with `<` or `>`; use `ct_lt()` or `ct_gt()` instead",
+          ),
+          BinOp::And(_) | BinOp::Or(_) => (
+            Code::SecretShortCircuit,
+            "cannot short-circuit on secret values with `&&` or `||`; use `&` or `|` on a \
+             `Choice` instead",
+          ),
+          _ => return,
+        };
+        if self.taint.mentions_secret(expr) {
+          self.error(code, expr, message);
+        }
+      },
+      Expr::If(ExprIf { cond, .. }) | Expr::While(ExprWhile { cond, .. })
+        if self.taint.mentions_secret(cond) =>
+      {
+        self.error(
+          Code::SecretBranch,
+          cond,
+          "cannot branch on a secret value; use `conditional_select()` instead",
+        );
+      },
+      Expr::Match(ExprMatch {
+        expr: scrutinee, ..
+      }) if self.taint.mentions_secret(scrutinee) => {
+        self.error(
+          Code::SecretMatch,
+          scrutinee,
+          "cannot `match` on a secret value",
+        );
+      },
+      Expr::Index(ExprIndex { index, .. }) if self.taint.mentions_secret(index) => {
+        self.error(Code::SecretIndex, index, "cannot index with a secret value");
+      },
+      _ => (),
+    }
+  }
+
+  /* Record an error if `expr` exits early depending on a secret value. */
+  fn check_branches(&mut self, expr: &Expr) {
+    match expr {
+      Expr::If(ExprIf {
+        cond,
+        then_branch,
+        else_branch,
+        ..
+      }) if self.taint.mentions_secret(cond) => {
+        self.check_if_arms(then_branch, else_branch);
+      },
+      Expr::Match(ExprMatch {
+        expr: scrutinee,
+        arms,
+        ..
+      }) if self.taint.mentions_secret(scrutinee) => {
+        for arm in arms {
+          self.check_arm(&arm.body);
+        }
+      },
+      Expr::Try(ExprTry { expr: inner, .. }) if self.taint.mentions_secret(inner) => {
+        self.error(
+          Code::SecretTry,
+          expr,
+          "cannot use `?` on a secret value, since it exits early depending on the value",
+        );
+      },
+      Expr::Index(ExprIndex { index, .. }) if self.taint.mentions_secret(index) => {
+        self.error(Code::SecretIndex, index, "cannot index with a secret value");
+      },
+      _ => (),
+    }
+  }
+
+  /* Record an error if `expr` is a loop whose trip count depends on a secret value. Only loops
+   * and the branches within them are checked, since a secret branch outside of any loop can't
+   * change how many times a loop runs. */
+  fn check_loops(&mut self, expr: &Expr, loop_depth: usize) {
+    match expr {
+      Expr::While(ExprWhile { cond, .. }) if self.taint.mentions_secret(cond) => {
+        self.error(
+          Code::SecretLoop,
+          cond,
+          "cannot loop on a secret condition; iterate a public number of times instead",
+        );
+      },
+      Expr::ForLoop(ExprForLoop { expr: iter, .. })
+        if matches!(&**iter, Expr::Range(_)) && self.taint.mentions_secret(iter) =>
+      {
+        self.error(
+          Code::SecretLoop,
+          iter,
+          "cannot loop over a range with secret bounds; iterate a public number of times instead",
+        );
+      },
+      Expr::If(ExprIf {
+        cond,
+        then_branch,
+        else_branch,
+        ..
+      }) if loop_depth > 0 && self.taint.mentions_secret(cond) => {
+        self.check_if_arms(then_branch, else_branch);
+      },
+      Expr::Match(ExprMatch {
+        expr: scrutinee,
+        arms,
+        ..
+      }) if loop_depth > 0 && self.taint.mentions_secret(scrutinee) => {
+        for arm in arms {
+          self.check_arm(&arm.body);
+        }
+      },
+      _ => (),
+    }
+  }
+}
+
+/* Walks a function body, tracking secret variables and the current loop depth. */
+struct Walker {
+  checker: Checker,
+  loop_depth: usize,
+}
+
+impl<'ast> Visit<'ast> for Walker {
+  fn visit_local(&mut self, local: &'ast Local) {
+    visit::visit_local(self, local);
+    self.checker.taint.bind_local(local);
+  }
+
+  /* Bindings within a block go out of scope at its end, along with any shadowing they did. */
+  fn visit_block(&mut self, block: &'ast Block) {
+    let outer = self.checker.taint.clone();
+    visit::visit_block(self, block);
+    self.checker.taint = outer;
+  }
+
+  fn visit_expr_for_loop(&mut self, expr: &'ast ExprForLoop) {
+    self.checker.taint.bind_for_loop(expr);
+    visit::visit_expr_for_loop(self, expr);
+  }
+
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    match self.checker.rules {
+      Rules::Impl => self.checker.check_impl(expr),
+      Rules::Branches => self.checker.check_branches(expr),
+      Rules::Loops => self.checker.check_loops(expr, self.loop_depth),
+    }
+    match expr {
+      Expr::Loop(_) | Expr::While(_) | Expr::ForLoop(_) => {
+        self.loop_depth += 1;
+        visit::visit_expr(self, expr);
+        self.loop_depth -= 1;
+      },
+      /* Control flow can't leave a closure, so loops outside of it don't matter. */
+      Expr::Closure(_) | Expr::Async(_) => {
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        visit::visit_expr(self, expr);
+        self.loop_depth = loop_depth;
+      },
+      _ => visit::visit_expr(self, expr),
+    }
+  }
+
+  /* Nested functions have their own arguments, and aren't checked. */
+  fn visit_item(&mut self, _item: &'ast Item) {}
+}
+
+/* Check `block` against `rules`, with the secret variables in `taint`. */
+fn check_block(rules: Rules, taint: Taint, block: &Block) -> Vec<Error> {
+  let mut walker = Walker {
+    checker: Checker {
+      rules,
+      taint,
+      errors: Vec::new(),
+    },
+    loop_depth: 0,
+  };
+  walker.visit_block(block);
+  walker.checker.errors
+}
+
+/* Check the body of each method in `item`, treating every argument as secret unless listed in a
+ * `#[ct_public_inputs(...)]` attribute on the method, and returning any errors as compile
+ * errors. */
+pub(crate) fn check_impl(item: &mut ItemImpl) -> proc_macro2::TokenStream {
+  let mut errors = Vec::new();
+  for item in item.items.iter_mut() {
+    if let ImplItem::Method(method) = item {
+      match take_public_inputs(&mut method.attrs) {
+        Ok(public) => {
+          let taint = Taint::from_all_inputs(&method.sig, &public.unwrap_or_default());
+          errors.extend(check_block(Rules::Impl, taint, &method.block));
+        },
+        Err(error) => errors.push(error),
+      }
+    }
+  }
+  let errors = errors.iter().map(Error::to_compile_error);
+  quote! { #(#errors)* }
+}
+
+/* Check the body of `item` against `rules`, with its secret arguments, returning any errors as
+ * compile errors. */
+pub(crate) fn check_fn(rules: Rules, item: &mut ItemFn) -> proc_macro2::TokenStream {
+  let errors = match Taint::from_fn(item) {
+    Ok(taint) => check_block(rules, taint, &item.block),
+    Err(error) => vec![error],
+  };
+  let errors = errors.iter().map(Error::to_compile_error);
+  quote! { #(#errors)* }
+}
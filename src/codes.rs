@@ -0,0 +1,88 @@
+//! Stable codes identifying each class of diagnostic produced by the macros.
+
+use proc_macro2::Span;
+use quote::ToTokens;
+use std::fmt;
+use syn::Error;
+
+/// A class of diagnostic, which is included in its message as e.g. `SUBTLE_DERIVE[E0007]`.
+///
+/// The discriminants are the published codes, so they must never be renumbered, and a code must
+/// never be reused for a different class once released. Every code is listed in the crate docs.
+#[derive(Clone, Copy)]
+pub(crate) enum Code {
+  /// The input is an enum or union, rather than a struct.
+  UnsupportedData = 1,
+  /// A `#[ct]` attribute isn't a list, as in `#[ct(...)]`.
+  MalformedAttribute = 2,
+  /// A `#[ct(...)]` option isn't recognized.
+  UnknownOption = 3,
+  /// A `#[ct(...)]` option was given a value of the wrong form.
+  InvalidOptionValue = 4,
+  /// A `#[ct(...)]` option requires a feature of this crate which isn't enabled.
+  MissingFeature = 5,
+  /// The macro or option doesn't support generic types.
+  GenericType = 6,
+  /// Two `#[ct(...)]` options can't be used together.
+  ConflictingOptions = 7,
+  /// A field with `#[cfg(...)]` isn't supported here.
+  UnsupportedCfgField = 8,
+  /// A field has a variable length, and isn't marked `#[ct(variable_length)]`.
+  VariableLength = 9,
+  /// A field's type isn't supported by the macro or option.
+  UnsupportedFieldType = 10,
+  /// A macro was asked to implement a trait it doesn't know.
+  UnsupportedTrait = 11,
+  /// The arguments of a macro are malformed.
+  InvalidMacroInput = 12,
+  /// An attribute macro was applied to an item it doesn't support.
+  UnsupportedItem = 13,
+  /// An attribute macro must be combined with another, which is missing.
+  MisplacedAttribute = 14,
+  /// Control flow branches on a secret value.
+  SecretBranch = 20,
+  /// A `match` inspects a secret value.
+  SecretMatch = 21,
+  /// A secret value is used as an index.
+  SecretIndex = 22,
+  /// Secret values are compared with a variable-time operator.
+  SecretComparison = 23,
+  /// A boolean operator short-circuits on a secret value.
+  SecretShortCircuit = 24,
+  /// Control flow could leave a branch taken on a secret value early.
+  EarlyExit = 25,
+  /// `?` is applied to a secret value.
+  SecretTry = 26,
+  /// The trip count of a loop depends on a secret value.
+  SecretLoop = 27,
+  /// A constant-time `if` has no `else` branch.
+  MissingElse = 28,
+  /// A public input names no argument of the function.
+  UnknownPublicInput = 29,
+}
+
+impl fmt::Display for Code {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "SUBTLE_DERIVE[E{:04}]", *self as u16)
+  }
+}
+
+impl Code {
+  /* Prefix `message` with this code. */
+  pub(crate) fn message(self, message: impl fmt::Display) -> String {
+    format!("{}: {}", self, message)
+  }
+
+  /* Abort the expansion with `message`, as the derives do for invalid input. */
+  pub(crate) fn panic(self, message: impl fmt::Display) -> ! { panic!("{}", self.message(message)) }
+
+  /* Produce a compile error with `message`, spanning `tokens`. */
+  pub(crate) fn error(self, tokens: impl ToTokens, message: impl fmt::Display) -> Error {
+    Error::new_spanned(tokens, self.message(message))
+  }
+
+  /* Produce a compile error with `message` at `span`. */
+  pub(crate) fn error_at(self, span: Span, message: impl fmt::Display) -> Error {
+    Error::new(span, self.message(message))
+  }
+}
@@ -0,0 +1,120 @@
+//! Rewriting of `if`/`else` on secret conditions to evaluate both arms, for `#[ct_equalize]`.
+
+use crate::{
+  analysis::{find_escape, Taint},
+  codes::Code,
+};
+use proc_macro2::Span;
+use quote::{quote, ToTokens};
+use syn::{
+  visit_mut::{self, VisitMut},
+  *,
+};
+
+struct Equalize {
+  taint: Taint,
+  errors: Vec<Error>,
+}
+
+impl Equalize {
+  fn error(&mut self, code: Code, tokens: impl ToTokens, message: &str) {
+    self.errors.push(code.error(tokens, message));
+  }
+
+  /* Produce a replacement for an `if` on a secret condition which evaluates both arms, or record
+   * why it is invalid. */
+  fn rewrite(&mut self, expr: &Expr) -> Option<Expr> {
+    match expr {
+      Expr::If(ExprIf {
+        cond,
+        then_branch,
+        else_branch,
+        ..
+      }) if self.taint.mentions_secret(cond) => {
+        let then_expr = Expr::Block(ExprBlock {
+          attrs: Vec::new(),
+          label: None,
+          block: then_branch.clone(),
+        });
+        let else_expr = match else_branch {
+          Some((_, else_expr)) => (**else_expr).clone(),
+          None => parse_quote!(()),
+        };
+        let mut is_valid = true;
+        for arm in [&then_expr, &else_expr] {
+          if let Some(escape) = find_escape(arm) {
+            self.error(
+              Code::EarlyExit,
+              escape,
+              "cannot leave an equalized `if` early, since both of its arms are always evaluated",
+            );
+            is_valid = false;
+          }
+        }
+        if !is_valid {
+          return None;
+        }
+        /* Use mixed-site hygiene so these locals can't shadow anything used by the arms. */
+        let span = Span::mixed_site();
+        Some(parse_quote_spanned! {span=>
+          {
+            let cond: bool = #cond;
+            let then_value = ::core::hint::black_box(#then_expr);
+            let else_value = ::core::hint::black_box(#else_expr);
+            if cond {
+              then_value
+            } else {
+              else_value
+            }
+          }
+        })
+      },
+      _ => None,
+    }
+  }
+}
+
+impl VisitMut for Equalize {
+  fn visit_local_mut(&mut self, local: &mut Local) {
+    visit_mut::visit_local_mut(self, local);
+    self.taint.bind_local(local);
+  }
+
+  /* Bindings within a block go out of scope at its end, along with any shadowing they did. */
+  fn visit_block_mut(&mut self, block: &mut Block) {
+    let outer = self.taint.clone();
+    visit_mut::visit_block_mut(self, block);
+    self.taint = outer;
+  }
+
+  fn visit_expr_for_loop_mut(&mut self, expr: &mut ExprForLoop) {
+    self.taint.bind_for_loop(expr);
+    visit_mut::visit_expr_for_loop_mut(self, expr);
+  }
+
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    /* Rewrite nested expressions first, so that `else if` chains are equalized throughout. */
+    visit_mut::visit_expr_mut(self, expr);
+    if let Some(replacement) = self.rewrite(expr) {
+      *expr = replacement;
+    }
+  }
+
+  /* Nested functions have their own arguments, and aren't rewritten. */
+  fn visit_item_mut(&mut self, _item: &mut Item) {}
+}
+
+/* Rewrite the body of `item`, returning any errors as compile errors. */
+pub(crate) fn rewrite_fn(item: &mut ItemFn) -> proc_macro2::TokenStream {
+  let taint = match Taint::from_fn(item) {
+    Ok(taint) => taint,
+    Err(error) => return error.to_compile_error(),
+  };
+  let mut equalize = Equalize {
+    taint,
+    errors: Vec::new(),
+  };
+  equalize.visit_block_mut(&mut item.block);
+  let errors = equalize.errors.iter().map(Error::to_compile_error);
+  quote! { #(#errors)* }
+}
@@ -1,4 +1,113 @@
 //! Derive macros for [`subtle`](https://docs.rs/subtle/latest/subtle/) traits.
+//!
+//! With the `runtime` feature enabled, types marked `#[ct(code_size)]` compare byte arrays by
+//! calling out-of-line functions from the
+//! [`subtle-derive-runtime`](https://docs.rs/subtle-derive-runtime) crate instead of expanding
+//! the comparisons inline, which keeps expansions small. Crates using that option must then depend
+//! on `subtle-derive-runtime` as well.
+//!
+//! Generated code refers to the traits from `::subtle`. A type being derived can name any other
+//! path to a crate providing the same traits, such as `subtle_ng` or a renamed or patched fork,
+//! with `#[ct(crate = "path")]`.
+//!
+//! The field comparisons are folded together with the `Iterated*` types from the `integration`
+//! branch of [cosmicexplorer/subtle](https://github.com/cosmicexplorer/subtle). With the
+//! `upstream-subtle` feature, they are instead folded into a
+//! [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html) directly, using
+//! only the traits published in `subtle` 2.x, so that
+//! [`ConstantTimeEq`](derive@ConstantTimeEq), [`ConstantTimeGreater`](derive@ConstantTimeGreater)
+//! and [`ConstantTimeLess`](derive@ConstantTimeLess) work with the crates.io release.
+//! [`ct_eq_chain!`] folds its comparisons the same way. The derives relying on `ConstantTimeOrd`
+//! still require the `integration` branch.
+//!
+//! Generated impls are emitted within an anonymous `const _: () = { ... };` item, as `serde` does,
+//! so that helper items they need never add names to the module they're expanded in. Generated
+//! trait impls are marked `#[automatically_derived]`, and every generated impl allows the lints
+//! its deliberate casts, indexing and fully qualified paths would otherwise trip, so the derives
+//! can be used from crates which deny them, including the restriction lints of `clippy` such as
+//! `clippy::as_conversions` and `clippy::indexing_slicing`. Generated functions returning a value
+//! without side effects are `#[must_use]`, and generated public items are documented:
+//!
+//!```
+//! #![deny(trivial_numeric_casts, unused_qualifications, unused_results)]
+//! use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, CtNew};
+//!
+//! /// A counter which is compared in constant time.
+//! #[derive(ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, CtNew)]
+//! pub struct Counter { hi: u32, lo: i8 }
+//!```
+//!
+//! Generated code refers to every item by its absolute path, such as `::core::cmp::Ordering`, and
+//! calls trait methods by their full paths, so it doesn't rely on the prelude at all. The derives
+//! work within `#[no_implicit_prelude]` modules, and alongside items shadowing prelude names:
+//!
+//!```
+//! #[no_implicit_prelude]
+//! mod strict {
+//!   #[derive(
+//!     ::subtle_derive::ConstantTimeEq,
+//!     ::subtle_derive::ConstantTimeGreater,
+//!     ::subtle_derive::ConstantTimeLess,
+//!     ::subtle_derive::ConstantTimeOrd,
+//!     ::subtle_derive::ConstEq,
+//!     ::subtle_derive::ConstPartialOrd,
+//!     ::subtle_derive::ConstOrd,
+//!   )]
+//!   pub struct Key { pub id: ::core::option::Option<u32>, pub flag: bool, pub bytes: [u8; 4] }
+//! }
+//!
+//! let key = strict::Key { id: Some(1), flag: true, bytes: [1, 2, 3, 4] };
+//! assert!(key == strict::Key { id: Some(1), flag: true, bytes: [1, 2, 3, 4] });
+//! assert!(key != strict::Key { id: None, flag: true, bytes: [1, 2, 3, 4] });
+//! assert!(key > strict::Key { id: None, flag: true, bytes: [1, 2, 3, 4] });
+//! assert_eq!(key.cmp(&key), core::cmp::Ordering::Equal);
+//!```
+//!
+//! The expansion of every macro is a pure function of its input tokens and the enabled features.
+//! It never depends on hash iteration order, on spans or source locations, on the environment, or
+//! on state kept between invocations, so building the same source twice produces identical code
+//! for audits and reproducible builds. The exact code generated may change between releases, but
+//! only in ways which preserve its documented behavior.
+//!
+//! Generated code is free of undefined behavior, and only contains `unsafe` where it is documented
+//! on the macro generating it, which is currently only [`macro@ct_fsm`]. Each such expansion also
+//! generates a `#[cfg(test)]` module exercising every path through its `unsafe` code, so that
+//! `cargo miri test` checks it in the crate using the macro. Comparisons of whole values as bytes,
+//! as with `#[ct(pod_bytes)]`, go through `bytemuck`'s safe API rather than generating `unsafe`
+//! code of their own.
+//!
+//! # Diagnostics
+//!
+//! Every error reported by the macros, other than errors in the syntax of Rust code they're given,
+//! starts with a stable code such as `SUBTLE_DERIVE[E0007]`, so that tooling can match on the class
+//! of error without parsing its message. Codes are never renumbered or reused once released.
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | `E0001` | The input is an enum or union, rather than a struct. |
+//! | `E0002` | A `#[ct]` attribute isn't a list, as in `#[ct(...)]`. |
+//! | `E0003` | A `#[ct(...)]` option isn't recognized. |
+//! | `E0004` | A `#[ct(...)]` option was given a value of the wrong form. |
+//! | `E0005` | A `#[ct(...)]` option requires a feature of this crate which isn't enabled. |
+//! | `E0006` | The macro or option doesn't support generic types. |
+//! | `E0007` | Two `#[ct(...)]` options can't be used together. |
+//! | `E0008` | A field with `#[cfg(...)]` isn't supported here. |
+//! | `E0009` | A field has a variable length; mark it `#[ct(variable_length)]` to compare it anyway. |
+//! | `E0010` | A field's type isn't supported by the macro or option. |
+//! | `E0011` | A macro was asked to implement a trait it doesn't know. |
+//! | `E0012` | The arguments of a macro are malformed. |
+//! | `E0013` | An attribute macro was applied to an item it doesn't support. |
+//! | `E0014` | An attribute macro must be combined with another, which is missing. |
+//! | `E0020` | Control flow branches on a secret value. |
+//! | `E0021` | A `match` inspects a secret value. |
+//! | `E0022` | A secret value is used as an index. |
+//! | `E0023` | Secret values are compared with a variable-time operator, such as `==` or `<`. |
+//! | `E0024` | `&&` or `||` short-circuits on a secret value. |
+//! | `E0025` | Control flow could leave a branch taken on a secret value early. |
+//! | `E0026` | `?` is applied to a secret value. |
+//! | `E0027` | The trip count of a loop depends on a secret value. |
+//! | `E0028` | A constant-time `if` has no `else` branch. |
+//! | `E0029` | A public input names no argument of the function. |
 
 #![warn(missing_docs)]
 #![deny(rustdoc::missing_crate_level_docs)]
@@ -6,305 +115,6752 @@
 #![doc(test(attr(deny(warnings))))]
 #![deny(clippy::all)]
 
+mod analysis;
+mod branchless;
+mod checks;
+mod codes;
+mod equalize;
+mod paths;
+
+use codes::Code;
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use quote::quote;
-use syn::*;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+  ext::IdentExt,
+  parse::{Parse, ParseStream},
+  spanned::Spanned,
+  *,
+};
 
-fn field_names(data: Data) -> Vec<String> {
+/* Get each field of the struct, along with the member naming it in e.g. `self.0`. */
+fn struct_fields(data: &Data) -> Vec<(Member, &Field)> {
   match data {
     Data::Struct(DataStruct { fields, .. }) => match fields {
-      /* Get the field names as strings. */
       Fields::Named(FieldsNamed { named, .. }) => named
         .iter()
-        .map(|Field { ident, .. }| {
-          ident
-            .as_ref()
-            .expect("named fields have idents")
-            .to_string()
+        .map(|field| {
+          let ident = field.ident.clone().expect("named fields have idents");
+          (Member::Named(ident), field)
         })
         .collect(),
-      /* If unnamed, get the indices of the fields as strings (this becomes e.g. `self.0`). */
+      /* If unnamed, get the indices of the fields (this becomes e.g. `self.0`). */
       Fields::Unnamed(FieldsUnnamed { unnamed, .. }) => unnamed
         .iter()
         .enumerate()
-        .map(|(i, _)| i.to_string())
+        .map(|(i, field)| {
+          /* The indices of later fields would depend on whether this one is compiled in. */
+          if !cfg_attrs(Some(field)).is_empty() {
+            Code::UnsupportedCfgField.panic("#[cfg(...)] is only supported on named fields");
+          }
+          (Member::Unnamed(Index::from(i)), field)
+        })
         .collect(),
       /* There are no fields to compare, so every instance is trivially equal. */
       Fields::Unit => Vec::new(),
     },
-    _ => panic!("this macro does not support enums or unions for constant-time operations"),
+    _ => Code::UnsupportedData
+      .panic("this macro does not support enums or unions for constant-time operations"),
+  }
+}
+
+/* Get the `#[cfg(...)]` attributes of a field, which the code generated for the field must carry
+ * too, so that it's compiled out along with the field. */
+fn cfg_attrs(field: Option<&Field>) -> Vec<&Attribute> {
+  field.map_or_else(Vec::new, |field| {
+    field
+      .attrs
+      .iter()
+      .filter(|attr| attr.path.is_ident("cfg"))
+      .collect()
+  })
+}
+
+/* Get the `#[cfg(...)]` attributes of each field, to place before e.g. its entry in a struct
+ * expression. */
+fn field_cfgs(fields: &[(Member, &Field)]) -> Vec<proc_macro2::TokenStream> {
+  fields
+    .iter()
+    .map(|(_, field)| {
+      let cfgs = cfg_attrs(Some(field));
+      quote! { #(#cfgs)* }
+    })
+    .collect()
+}
+
+/* Wrap the statements generated for a field in a block guarded by its `#[cfg(...)]` attributes, if
+ * it has any. */
+fn cfg_guarded(
+  field: Option<&Field>,
+  stmts: Vec<proc_macro2::TokenStream>,
+) -> Vec<proc_macro2::TokenStream> {
+  let cfgs = cfg_attrs(field);
+  if cfgs.is_empty() {
+    stmts
+  } else {
+    vec![quote! { #(#cfgs)* { #(#stmts)* } }]
+  }
+}
+
+/// Options parsed from `#[ct(...)]` attributes on the type being derived.
+#[derive(Default)]
+struct ContainerAttrs {
+  /// Which `Const*` derives should also generate the constant-time trait impls they rely on.
+  derive_ct: DeriveCt,
+  /// Whether to generate a `ct_ne()` override alongside `ct_eq()`.
+  ct_ne: bool,
+  /// Whether integer fields are encoded as big-endian rather than little-endian bytes.
+  big_endian: bool,
+  /// Whether to compare the whole value as the bytes of a `bytemuck::Pod` type.
+  pod_bytes: bool,
+  /// Whether to also implement the derived trait for the type's `rkyv` archived form.
+  archived: bool,
+  /// The path to the crate providing the subtle traits, if not `::subtle`.
+  krate: Option<Path>,
+  /// Whether to also generate a constructor decoding hex strings.
+  hex: bool,
+  /// Whether to compare runs of fields with the same type in loops.
+  chunked: bool,
+  /// Whether to collect the comparison of each field into an array of `Choice`s before folding it.
+  choice_array: bool,
+  /// Whether to share out-of-line helpers between comparisons, rather than expanding them inline.
+  code_size: bool,
+  /// Whether to pass the results of comparisons through an optimization barrier.
+  opt_barrier: bool,
+  /// The `#[inline]` attribute to put on generated trait methods.
+  inline: Inline,
+  /// The properties checked by the `CtTest` derive, beyond those of `ct_eq()`.
+  tests: CtTests,
+  /// The options given to the `CtVerify` derive with `#[ct(verify(...))]`.
+  verify: Option<Vec<NestedMeta>>,
+  /// Whether to also generate Kani proof harnesses for the derived comparison.
+  kani: bool,
+  /// Whether to check the derived comparison against `==` on each field in debug builds.
+  debug_check: bool,
+  /// Whether the `Secret` derive should also zeroize the value when it's dropped.
+  zeroize: bool,
+}
+
+/// The `Const*` derives which generate the constant-time trait impls they rely on, from
+/// `#[ct(eq(derive_ct))]`, `#[ct(partial_ord(derive_ct))]` and `#[ct(ord(derive_ct))]`.
+#[derive(Default)]
+struct DeriveCt {
+  /// Whether `ConstEq` generates `ConstantTimeEq`.
+  eq: bool,
+  /// Whether `ConstPartialOrd` generates `ConstantTimeGreater` and `ConstantTimeLess`.
+  partial_ord: bool,
+  /// Whether `ConstOrd` generates `ConstantTimeOrd`.
+  ord: bool,
+}
+
+/// Properties of the constant-time traits which the `CtTest` derive can check, from `#[ct(test(...))]`.
+#[derive(Default)]
+struct CtTests {
+  /// Whether `ct_eq()` agrees with `==`.
+  partial_eq: bool,
+  /// Whether exactly one of `ct_eq()`, `ct_gt()` and `ct_lt()` holds.
+  ord: bool,
+  /// Whether `ct_cmp()` agrees with `ct_gt()` and `ct_lt()`.
+  cmp: bool,
+  /// Whether to check every pair of values, rather than sampling them.
+  exhaustive: bool,
+  /// The inputs for an ignored test comparing the timing of `ct_eq()` on two classes of inputs.
+  timing: Option<TimingTest>,
+}
+
+/// The options of the timing test generated by `CtTest`, from `#[ct(test(timing(...)))]`.
+struct TimingTest {
+  /// A function returning a pair of equal values.
+  equal: Path,
+  /// A function returning a pair of values which differ in as many places as possible.
+  different: Path,
+  /// The number of measurements to take for each class.
+  samples: LitInt,
+}
+
+/// How generated trait methods should be inlined.
+#[derive(Default)]
+enum Inline {
+  /// Hint that they should be inlined, with `#[inline]`.
+  #[default]
+  Hint,
+  /// Always inline them, with `#[inline(always)]`.
+  Always,
+  /// Never inline them, with `#[inline(never)]`.
+  Never,
+  /// Leave it to the compiler, without any attribute.
+  Default,
+}
+
+/* Wrap generated impls in an anonymous `const` item, so that any helper items they need are never
+ * visible from the module they're expanded in. */
+fn anonymous_scope(output: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  quote! {
+    const _: () = {
+      #output
+    };
+  }
+}
+
+/* Convert the output of a derive into a TokenStream within an anonymous scope, pointing its paths
+ * into `::subtle` at the crate selected by `#[ct(crate = "...")]` instead. */
+fn with_subtle_path(output: proc_macro2::TokenStream, krate: Option<&Path>) -> TokenStream {
+  let subtle = paths::subtle_path(krate);
+  paths::rewrite_subtle_paths(anonymous_scope(output), &subtle).into()
+}
+
+/* Get the arguments of every `#[ct(...)]` attribute, in order. */
+fn ct_metas(attrs: &[Attribute]) -> Vec<NestedMeta> {
+  attrs
+    .iter()
+    .filter(|attr| attr.path.is_ident("ct"))
+    .flat_map(|attr| match attr.parse_meta() {
+      Ok(Meta::List(MetaList { nested, .. })) => nested.into_iter(),
+      _ => Code::MalformedAttribute
+        .panic("expected the #[ct] attribute to be a list, as in #[ct(...)]"),
+    })
+    .collect()
+}
+
+fn container_attrs(attrs: &[Attribute]) -> ContainerAttrs {
+  let mut ret = ContainerAttrs::default();
+  for meta in ct_metas(attrs) {
+    match meta {
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("ct_ne") => {
+        ret.ct_ne = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("big_endian") => {
+        ret.big_endian = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("chunked") => {
+        ret.chunked = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("zeroize") => {
+        ret.zeroize = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("choice_array") => {
+        ret.choice_array = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("code_size") => {
+        if !cfg!(feature = "runtime") {
+          Code::MissingFeature.panic("#[ct(code_size)] requires the runtime feature");
+        }
+        ret.code_size = true;
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Str(ref lit),
+        ..
+      }))
+        if path.is_ident("inline") =>
+      {
+        ret.inline = match lit.value().as_str() {
+          "always" => Inline::Always,
+          "never" => Inline::Never,
+          "default" => Inline::Default,
+          value => Code::InvalidOptionValue.panic(format_args!(
+            "unrecognized #[ct(inline = \"{}\")]; expected \"always\", \"never\" or \"default\"",
+            value
+          )),
+        };
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("debug_check") => {
+        ret.debug_check = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("kani") => {
+        ret.kani = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("opt_barrier") => {
+        ret.opt_barrier = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("hex") => {
+        ret.hex = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("pod_bytes") => {
+        ret.pod_bytes = true;
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Str(ref lit),
+        ..
+      }))
+        if path.is_ident("crate") =>
+      {
+        ret.krate = Some(lit.parse().unwrap_or_else(|_| {
+          Code::InvalidOptionValue
+            .panic("#[ct(crate = \"...\")] must be a path, as in \"::subtle\"")
+        }));
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("archived") => {
+        if !cfg!(feature = "rkyv") {
+          Code::MissingFeature.panic("#[ct(archived)] requires the rkyv feature");
+        }
+        ret.archived = true;
+      },
+      NestedMeta::Meta(Meta::List(MetaList {
+        ref path,
+        ref nested,
+        ..
+      }))
+        if path.is_ident("verify") =>
+      {
+        ret.verify = Some(nested.iter().cloned().collect());
+      },
+      NestedMeta::Meta(Meta::List(MetaList {
+        ref path,
+        ref nested,
+        ..
+      }))
+        if path.is_ident("test") =>
+      {
+        for test in nested {
+          match test {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("partial_eq") => {
+              ret.tests.partial_eq = true;
+            },
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("ord") => {
+              ret.tests.ord = true;
+            },
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("cmp") => {
+              ret.tests.cmp = true;
+            },
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("exhaustive") => {
+              ret.tests.exhaustive = true;
+            },
+            NestedMeta::Meta(Meta::List(MetaList { path, nested, .. }))
+              if path.is_ident("timing") =>
+            {
+              ret.tests.timing = Some(timing_test(nested));
+            },
+            test => Code::UnknownOption.panic(format_args!(
+              "unrecognized #[ct(test({}))]; expected partial_eq, ord, cmp, exhaustive or \
+               timing(...)",
+              quote!(#test)
+            )),
+          }
+        }
+      },
+      NestedMeta::Meta(Meta::List(MetaList {
+        ref path,
+        ref nested,
+        ..
+      }))
+        if path.is_ident("eq") || path.is_ident("partial_ord") || path.is_ident("ord") =>
+      {
+        let derive_ct = if path.is_ident("eq") {
+          &mut ret.derive_ct.eq
+        } else if path.is_ident("partial_ord") {
+          &mut ret.derive_ct.partial_ord
+        } else {
+          &mut ret.derive_ct.ord
+        };
+        for option in nested {
+          match option {
+            NestedMeta::Meta(Meta::Path(option)) if option.is_ident("derive_ct") => {
+              *derive_ct = true;
+            },
+            option => Code::UnknownOption.panic(format_args!(
+              "unrecognized #[ct({}({}))]; expected derive_ct",
+              quote!(#path),
+              quote!(#option)
+            )),
+          }
+        }
+      },
+      meta => Code::UnknownOption.panic(format_args!(
+        "unrecognized #[ct(...)] option on type: {}",
+        quote!(#meta)
+      )),
+    }
+  }
+  ret
+}
+
+/* Parse the options of `#[ct(test(timing(...)))]`. */
+fn timing_test(nested: &punctuated::Punctuated<NestedMeta, Token![,]>) -> TimingTest {
+  let (mut equal, mut different) = (None, None);
+  let mut samples = LitInt::new("10_000", Span::call_site());
+  for meta in nested {
+    match meta {
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        path,
+        lit: Lit::Str(lit),
+        ..
+      }))
+        if path.is_ident("equal") || path.is_ident("different") =>
+      {
+        let generator = Some(lit.parse::<Path>().unwrap_or_else(|_| {
+          Code::InvalidOptionValue.panic("#[ct(test(timing(...)))] generators must be paths")
+        }));
+        if path.is_ident("equal") {
+          equal = generator;
+        } else {
+          different = generator;
+        }
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        path,
+        lit: Lit::Int(lit),
+        ..
+      }))
+        if path.is_ident("samples") =>
+      {
+        samples = lit.clone();
+      },
+      meta => Code::UnknownOption.panic(format_args!(
+        "unrecognized #[ct(test(timing({})))]; expected `equal = \"...\"`, \
+         `different = \"...\"` or `samples = N`",
+        quote!(#meta)
+      )),
+    }
+  }
+  match (equal, different) {
+    (Some(equal), Some(different)) => TimingTest {
+      equal,
+      different,
+      samples,
+    },
+    _ => Code::InvalidOptionValue
+      .panic("#[ct(test(timing(...)))] requires both `equal = \"...\"` and `different = \"...\"`"),
+  }
+}
+
+/// How to compare a floating-point field, which `subtle` doesn't implement its traits for.
+#[derive(Clone, Copy)]
+enum FloatCompare {
+  /// Compare the raw bit patterns from `to_bits()`.
+  Bits,
+  /// Compare in IEEE 754 `totalOrder`, as with `total_cmp()`.
+  TotalOrder,
+}
+
+/// Options parsed from `#[ct(...)]` attributes on a single field.
+#[derive(Clone, Default)]
+struct FieldAttrs {
+  /// Whether the field's value may be shown by a redacted `Debug` impl.
+  debug_public: bool,
+  /// Functions returning a `Choice` which must all hold for the field's value to be valid.
+  checks: Vec<Path>,
+  /// A range of values the field must fall within to be valid.
+  range: Option<ExprRange>,
+  /// The width of the field's byte encoding, if it cannot be inferred from its type.
+  bytes: Option<Expr>,
+  /// How to compare the field, if it is a float.
+  float: Option<FloatCompare>,
+  /// Whether the field may be compared despite its length being observable.
+  variable_length: bool,
+  /// Whether a byte array field is compared 8 bytes at a time, as `u64` words.
+  words: bool,
+  /// Whether an `arrayvec::ArrayVec` field is compared over its full capacity.
+  array_vec: bool,
+  /// Every value of the field, for the exhaustive tests generated by `CtTest`.
+  domain: Option<Expr>,
+  /// The fields of this field to compare in its place, from `#[ct(flatten(...))]`.
+  flatten: Option<Vec<Member>>,
+  /// The maximum number of nodes in a list linked by this `Option<Box<Self>>` field.
+  max_depth: Option<usize>,
+}
+
+fn field_attrs(attrs: &[Attribute]) -> FieldAttrs {
+  let mut ret = FieldAttrs::default();
+  for meta in ct_metas(attrs) {
+    match meta {
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("debug_public") => {
+        ret.debug_public = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("float_bits") => {
+        ret.float = Some(FloatCompare::Bits);
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("float_total_order") => {
+        ret.float = Some(FloatCompare::TotalOrder);
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("variable_length") => {
+        ret.variable_length = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("words") => {
+        ret.words = true;
+      },
+      NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("array_vec") => {
+        ret.array_vec = true;
+      },
+      NestedMeta::Meta(Meta::List(MetaList {
+        ref path,
+        ref nested,
+        ..
+      }))
+        if path.is_ident("flatten") =>
+      {
+        let members = nested.iter().map(|inner| match inner {
+          NestedMeta::Meta(Meta::Path(path)) => match path.get_ident() {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Code::InvalidOptionValue.panic(format_args!(
+              "#[ct(flatten(...))] expects field names or indices, not {}",
+              quote!(#path)
+            )),
+          },
+          NestedMeta::Lit(Lit::Int(index)) => Member::Unnamed(Index {
+            index: index.base10_parse().unwrap_or_else(|_| {
+              Code::InvalidOptionValue.panic("#[ct(flatten(...))] indices must be integers")
+            }),
+            span: index.span(),
+          }),
+          inner => Code::InvalidOptionValue.panic(format_args!(
+            "#[ct(flatten(...))] expects field names or indices, not {}",
+            quote!(#inner)
+          )),
+        });
+        ret.flatten = Some(members.collect());
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Int(ref lit),
+        ..
+      }))
+        if path.is_ident("max_depth") =>
+      {
+        ret.max_depth = Some(
+          lit
+            .base10_parse()
+            .ok()
+            .filter(|depth| *depth > 0)
+            .unwrap_or_else(|| {
+              Code::InvalidOptionValue.panic("#[ct(max_depth = N)] must be a positive integer")
+            }),
+        );
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Str(ref lit),
+        ..
+      }))
+        if path.is_ident("check") =>
+      {
+        ret.checks.push(lit.parse().unwrap_or_else(|_| {
+          Code::InvalidOptionValue.panic("#[ct(check = \"...\")] must be a path")
+        }));
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Str(ref lit),
+        ..
+      }))
+        if path.is_ident("domain") =>
+      {
+        ret.domain = Some(lit.parse().unwrap_or_else(|_| {
+          Code::InvalidOptionValue.panic("#[ct(domain = \"...\")] must be an expression")
+        }));
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path,
+        lit: Lit::Str(ref lit),
+        ..
+      }))
+        if path.is_ident("range") =>
+      {
+        match lit.parse() {
+          Ok(Expr::Range(range)) => {
+            ret.range = Some(range);
+          },
+          _ => Code::InvalidOptionValue.panic("#[ct(range = \"...\")] must be a range expression"),
+        }
+      },
+      NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+        ref path, ref lit, ..
+      }))
+        if path.is_ident("bytes") =>
+      {
+        ret.bytes = Some(match lit {
+          Lit::Int(lit) => parse_quote! { #lit },
+          Lit::Str(lit) => lit.parse().unwrap_or_else(|_| {
+            Code::InvalidOptionValue.panic("#[ct(bytes = \"...\")] must be an expression")
+          }),
+          _ => Code::InvalidOptionValue.panic("#[ct(bytes = ...)] must be an integer or a string"),
+        });
+      },
+      meta => Code::UnknownOption.panic(format_args!(
+        "unrecognized #[ct(...)] option on field: {}",
+        quote!(#meta)
+      )),
+    }
+  }
+  ret
+}
+
+/* Generate an expression checking whether `value` (a reference) lies within `range`, by comparing
+ * it against each bound that is present without branching. */
+fn range_check(value: &Expr, range: &ExprRange) -> proc_macro2::TokenStream {
+  let ExprRange {
+    from, limits, to, ..
+  } = range;
+  let lower = from.as_ref().map(|lo| {
+    quote! { !::subtle::ConstantTimeLess::ct_lt(#value, &(#lo)) }
+  });
+  let upper = to.as_ref().map(|hi| match limits {
+    RangeLimits::HalfOpen(_) => quote! { ::subtle::ConstantTimeLess::ct_lt(#value, &(#hi)) },
+    RangeLimits::Closed(_) => quote! { !::subtle::ConstantTimeGreater::ct_gt(#value, &(#hi)) },
+  });
+  let bounds: Vec<proc_macro2::TokenStream> = lower.into_iter().chain(upper).collect();
+  quote! { <::subtle::Choice as ::core::convert::From<u8>>::from(1u8) #(& #bounds)* }
+}
+
+/* Generate statements which `&=` the validity of each field (with the value bound to the local
+ * variable `local`) into the hygienic local `is_valid`. */
+fn validity_stmts<'a>(
+  fields: impl Iterator<Item=(&'a Ident, &'a Field)>,
+) -> Vec<proc_macro2::TokenStream> {
+  let is_valid = Ident::new("is_valid", Span::mixed_site());
+  fields
+    .flat_map(|(local, field)| {
+      let FieldAttrs { checks, range, .. } = field_attrs(&field.attrs);
+      let value: Expr = parse_quote! { &#local };
+      let range = range.map(|range| range_check(&value, &range));
+      let stmts = checks
+        .into_iter()
+        .map(|check| quote! { #check(#value) })
+        .chain(range)
+        .map(|choice| quote! { #is_valid &= #choice; })
+        .collect();
+      cfg_guarded(Some(field), stmts)
+    })
+    .collect()
+}
+
+/* Get a local variable name for each field, to use as e.g. a constructor argument. */
+fn field_locals(data: &Data) -> Vec<Ident> {
+  struct_fields(data)
+    .into_iter()
+    .map(|(name, _)| member_local(&name))
+    .collect()
+}
+
+/* Get a local variable name for the field named by `member`: the field name itself if it has one,
+ * or else `field_{index}`. */
+fn member_local(member: &Member) -> Ident {
+  match member {
+    Member::Named(ident) => ident.clone(),
+    Member::Unnamed(index) => format_ident!("field_{}", index.index),
+  }
+}
+
+/* How a single field is laid out in a fixed-width byte encoding. */
+enum ByteField {
+  /* A primitive integer, encoded with to_{le,be}_bytes(). */
+  Int,
+  /* A byte array, copied as-is. */
+  Bytes,
+  /* Any other type, which must have its own from_bytes()/to_bytes() methods. */
+  Nested,
+}
+
+/* A field within a fixed-width byte encoding, along with its width and offset as const
+ * expressions. */
+struct ByteSlot<'a> {
+  member: Member,
+  local: Ident,
+  field: &'a Field,
+  kind: ByteField,
+  width: proc_macro2::TokenStream,
+  offset: proc_macro2::TokenStream,
+}
+
+fn is_primitive_int(ty: &Type) -> bool {
+  const INTS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+  ];
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => INTS.iter().any(|int| path.is_ident(int)),
+    _ => false,
+  }
+}
+
+fn is_u8(ty: &Type) -> bool {
+  matches!(ty, Type::Path(TypePath { qself: None, path }) if path.is_ident("u8"))
+}
+
+/* Lay out every field one after another in declaration order. Returns each field's slot along
+ * with the total width of the encoding. */
+fn byte_layout(data: &Data) -> (Vec<ByteSlot<'_>>, proc_macro2::TokenStream) {
+  let mut offset = quote! { 0 };
+  let slots = struct_fields(data)
+    .into_iter()
+    .zip(field_locals(data))
+    .map(|((name, field), local)| {
+      if !cfg_attrs(Some(field)).is_empty() {
+        Code::UnsupportedCfgField
+          .panic("#[cfg(...)] fields can't be encoded as bytes, since the layout is fixed");
+      }
+      let (kind, width) = match (field_attrs(&field.attrs).bytes, &field.ty) {
+        (Some(width), _) => (ByteField::Nested, quote! { (#width) }),
+        (None, Type::Array(TypeArray { elem, len, .. })) if is_u8(elem) => {
+          (ByteField::Bytes, quote! { (#len) })
+        },
+        (None, ty) if is_primitive_int(ty) => {
+          (ByteField::Int, quote! { ::core::mem::size_of::<#ty>() })
+        },
+        _ => Code::UnsupportedFieldType.panic(format_args!(
+          "field {} needs #[ct(bytes = N)] to be encoded as bytes, since its width is unknown",
+          quote!(#name)
+        )),
+      };
+      let slot = ByteSlot {
+        member: name,
+        local,
+        field,
+        kind,
+        width: width.clone(),
+        offset: offset.clone(),
+      };
+      offset = quote! { #offset + #width };
+      slot
+    })
+    .collect();
+  (slots, offset)
+}
+
+/* The members leading from `self` to a compared value: a single field of the struct, or with
+ * `#[ct(flatten(...))]`, a field of one of its fields. */
+#[derive(Clone)]
+struct FieldPath(Vec<Member>);
+
+impl FieldPath {
+  /* Whether this is a field of a flattened field, whose type is unknown. */
+  fn is_flattened(&self) -> bool { self.0.len() > 1 }
+
+  /* Get a local variable name for the value, as member_local() does for a single field. */
+  fn local(&self) -> Ident {
+    match self.0.as_slice() {
+      [member] => member_local(member),
+      members => {
+        let locals: Vec<String> = members
+          .iter()
+          .map(|member| member_local(member).unraw().to_string())
+          .collect();
+        format_ident!("{}", locals.join("_"))
+      },
+    }
+  }
+}
+
+impl quote::ToTokens for FieldPath {
+  fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+    let members = &self.0;
+    tokens.extend(quote!(#(#members).*));
+  }
+}
+
+/* Get each field of the struct, for comparison by iterated_block(). Fields with
+ * `#[ct(flatten(...))]` are replaced by each of the fields they list, which keep the flattened
+ * field's `#[cfg(...)]` attributes. */
+fn compared_fields(data: &Data) -> Vec<(FieldPath, Option<&Field>)> {
+  struct_fields(data)
+    .into_iter()
+    .flat_map(|(name, field)| match field_attrs(&field.attrs).flatten {
+      Some(inner) => inner
+        .into_iter()
+        .map(|member| (FieldPath(vec![name.clone(), member]), Some(field)))
+        .collect(),
+      None => vec![(FieldPath(vec![name]), Some(field))],
+    })
+    .collect()
+}
+
+/* If `ty` is `Wrapper<T>` for the given wrapper type name, get `T`. */
+fn generic_inner<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => {
+      let segment = path.segments.last()?;
+      match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+          if segment.ident == wrapper && args.len() == 1 =>
+        {
+          match args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+          }
+        },
+        _ => None,
+      }
+    },
+    _ => None,
+  }
+}
+
+/* If `ty` is `Option<T>`, get `T`. */
+fn option_inner(ty: &Type) -> Option<&Type> { generic_inner(ty, "Option") }
+
+/* If `ty` is a `NonZero*` integer type, get the primitive integer type it wraps. */
+fn nonzero_inner(ty: &Type) -> Option<Type> {
+  if let Some(inner) = generic_inner(ty, "NonZero") {
+    return Some(inner.clone());
+  }
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => {
+      let segment = path.segments.last()?;
+      let int = segment
+        .ident
+        .to_string()
+        .strip_prefix("NonZero")?
+        .to_lowercase();
+      if int.is_empty() {
+        return None;
+      }
+      let int = format_ident!("{}", int, span = segment.ident.span());
+      let int: Type = parse_quote!(#int);
+      Some(int).filter(|int| segment.arguments.is_empty() && is_primitive_int(int))
+    },
+    _ => None,
+  }
+}
+
+fn is_named_type(ty: &Type, name: &str) -> bool {
+  matches!(ty, Type::Path(TypePath { qself: None, path }) if path.is_ident(name))
+}
+
+/* If `ty` is a signed integer type (other than `i128`), convert `value` to an unsigned integer in
+ * the same order, by flipping the sign bit. */
+fn signed_to_unsigned(
+  ty: &Type,
+  value: &proc_macro2::TokenStream,
+) -> Option<proc_macro2::TokenStream> {
+  const SIGNED: &[(&str, &str)] = &[
+    ("i8", "u8"),
+    ("i16", "u16"),
+    ("i32", "u32"),
+    ("i64", "u64"),
+    ("isize", "u64"),
+  ];
+  let (signed, unsigned) = SIGNED
+    .iter()
+    .find(|(signed, _)| is_named_type(ty, signed))?;
+  Some(if *signed == "isize" {
+    /* `subtle` doesn't order `usize`, so widen `isize` first. */
+    quote! { ((#value) as i64 as u64 ^ i64::MIN as u64) }
+  } else {
+    let (signed, unsigned) = (format_ident!("{}", signed), format_ident!("{}", unsigned));
+    quote! { ((#value) as #unsigned ^ #signed::MIN as #unsigned) }
+  })
+}
+
+/* If `ty` is `Cow<'a, B>`, get `B`. */
+fn cow_inner(ty: &Type) -> Option<&Type> {
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => {
+      let segment = path.segments.last()?;
+      match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+          if segment.ident == "Cow" =>
+        {
+          match args.last()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+          }
+        },
+        _ => None,
+      }
+    },
+    _ => None,
+  }
+}
+
+/* If `ty` is `Wrapper<T, N>` for the given wrapper type name, as with the fixed-capacity
+ * collections, get `T` and `N`. */
+fn sized_generic_args<'a>(ty: &'a Type, wrapper: &str) -> Option<(&'a Type, &'a GenericArgument)> {
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => {
+      let segment = path.segments.last()?;
+      match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+          if segment.ident == wrapper && args.len() == 2 =>
+        {
+          match (args.first()?, args.last()?) {
+            (GenericArgument::Type(inner), size) => Some((inner, size)),
+            _ => None,
+          }
+        },
+        _ => None,
+      }
+    },
+    _ => None,
+  }
+}
+
+/* If `ty` is `generic_array::GenericArray<T, N>` or `hybrid_array::Array<T, N>`, with the
+ * corresponding feature enabled, get `T`. */
+fn fixed_array_elem(ty: &Type) -> Option<&Type> {
+  let generic_array = if cfg!(feature = "generic-array") {
+    sized_generic_args(ty, "GenericArray")
+  } else {
+    None
+  };
+  let hybrid_array = if cfg!(feature = "hybrid-array") {
+    sized_generic_args(ty, "Array")
+  } else {
+    None
+  };
+  generic_array.or(hybrid_array).map(|(elem, _)| elem)
+}
+
+/* If `ty` is `secrecy::SecretBox<T>`, or one of its aliases `SecretString` and `SecretSlice<T>`,
+ * with the `secrecy` feature, get `T`. */
+fn secret_box_inner(ty: &Type) -> Option<Type> {
+  if !cfg!(feature = "secrecy") {
+    None
+  } else if is_path_ending_in(ty, "SecretString") {
+    Some(parse_quote!(str))
+  } else if let Some(elem) = generic_inner(ty, "SecretSlice") {
+    Some(parse_quote!([#elem]))
+  } else {
+    generic_inner(ty, "SecretBox").cloned()
+  }
+}
+
+/* If `ty` is `Vec<T>` or `[T]` (or `heapless::Vec<T, N>` or `smallvec::SmallVec<[T; N]>`, with
+ * the corresponding feature), get `T`. */
+fn sequence_elem(ty: &Type) -> Option<&Type> {
+  match ty {
+    Type::Slice(TypeSlice { elem, .. }) => Some(elem),
+    ty if cfg!(feature = "heapless") && sized_generic_args(ty, "Vec").is_some() => {
+      sized_generic_args(ty, "Vec").map(|(elem, _)| elem)
+    },
+    ty if cfg!(feature = "smallvec") && generic_inner(ty, "SmallVec").is_some() => {
+      match generic_inner(ty, "SmallVec")? {
+        Type::Array(TypeArray { elem, .. }) => Some(elem),
+        _ => None,
+      }
+    },
+    ty => generic_inner(ty, "Vec"),
+  }
+}
+
+/* Whether `ty` is a path ending in the non-generic type `name`, as in `core::time::Duration`. */
+fn is_path_ending_in(ty: &Type, name: &str) -> bool {
+  match ty {
+    Type::Path(TypePath { qself: None, path }) => path.segments.last().map_or(false, |segment| {
+      segment.ident == name && segment.arguments.is_empty()
+    }),
+    _ => false,
+  }
+}
+
+fn is_duration(ty: &Type) -> bool { is_path_ending_in(ty, "Duration") }
+
+/* Whether `ty` is `bytes::Bytes` or `bytes::BytesMut`, with the `bytes` feature. */
+fn is_bytes_buf(ty: &Type) -> bool {
+  cfg!(feature = "bytes") && (is_path_ending_in(ty, "Bytes") || is_path_ending_in(ty, "BytesMut"))
+}
+
+fn is_bool(ty: &Type) -> bool {
+  matches!(ty, Type::Path(TypePath { qself: None, path }) if path.is_ident("bool"))
+}
+
+/// Statements applying pairs of operands to an iterated comparison named `ret`.
+struct Comparison<'a> {
+  /// The name of the `Iterated*` method to apply each pair of operands with.
+  apply: &'a str,
+  /// Whether to call out-of-line helpers from `subtle-derive-runtime`, for `#[ct(code_size)]`.
+  code_size: bool,
+  /// Whether to zeroize temporary copies of the operands, for `#[ct(zeroize)]`.
+  zeroize: bool,
+  /// The span of the field being compared, which errors about its type should point at.
+  span: Span,
+  /// The statements generated so far.
+  stmts: Vec<proc_macro2::TokenStream>,
+}
+
+impl Comparison<'_> {
+  /* Apply a pair of places or values which implement the subtle traits. */
+  fn compare(&mut self, a: impl quote::ToTokens, b: impl quote::ToTokens) {
+    let (a, b) = (quote!(#a), quote!(#b));
+    if cfg!(feature = "upstream-subtle") {
+      let stmts = folded_stmts(self.apply, &a, &b, self.span);
+      self.stmts.extend(stmts);
+    } else {
+      /* Call the method by its full path, so that no other method with the same name can be picked
+       * instead. */
+      let iterated = match self.apply {
+        "apply_eq" => "IteratedEq",
+        "apply_ne" => "IteratedNe",
+        "apply_gt" => "IteratedGreater",
+        "apply_lt" => "IteratedLess",
+        apply => unreachable!("unknown comparison {}", apply),
+      };
+      let span = self.span;
+      let iterated = format_ident!("{}", iterated, span = span);
+      let apply = format_ident!("{}", self.apply, span = span);
+      let ret = Ident::new("ret", Span::mixed_site());
+      self
+        .stmts
+        .push(quote_spanned! {span=> ::subtle::#iterated::#apply(&mut #ret, &#a, &#b); });
+    }
+  }
+}
+
+/* With the `upstream-subtle` feature, get the statements folding a pair of operands directly into
+ * the `Choice` named `ret`, in place of the `Iterated*` method `apply`. The ordering comparisons
+ * also track whether every pair so far was equal in `eq`, so that only the first unequal pair
+ * decides the result. Both are hygienic locals, so the operands can't refer to them. */
+fn folded_stmts(
+  apply: &str,
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  span: Span,
+) -> Vec<proc_macro2::TokenStream> {
+  let ret = Ident::new("ret", Span::mixed_site());
+  let eq = Ident::new("eq", Span::mixed_site());
+  let ordering = |method: proc_macro2::TokenStream| {
+    vec![
+      quote_spanned! {span=> #ret |= #eq & ::subtle::#method(&#a, &#b); },
+      quote_spanned! {span=> #eq &= ::subtle::ConstantTimeEq::ct_eq(&#a, &#b); },
+    ]
+  };
+  match apply {
+    "apply_eq" => {
+      vec![quote_spanned! {span=> #ret &= ::subtle::ConstantTimeEq::ct_eq(&#a, &#b); }]
+    },
+    "apply_ne" => {
+      vec![quote_spanned! {span=> #ret |= ::subtle::ConstantTimeEq::ct_ne(&#a, &#b); }]
+    },
+    "apply_gt" => ordering(quote_spanned!(span=> ConstantTimeGreater::ct_gt)),
+    "apply_lt" => ordering(quote_spanned!(span=> ConstantTimeLess::ct_lt)),
+    _ => unreachable!("unknown comparison {}", apply),
+  }
+}
+
+/* The primitive types which `rkyv` archives in a fixed byte order, and which must be converted back
+ * with `to_native()` to be compared. */
+const ARCHIVED_NATIVE: &[&str] = &[
+  "u16", "u32", "u64", "u128", "i16", "i32", "i64", "i128", "f32", "f64", "char",
+];
+
+/* Get the fields of the `rkyv` archived form of a type with the given fields, where the type of
+ * each field `T` becomes `::rkyv::Archived<T>`. */
+fn archived_data(data: &Data) -> Data {
+  let mut data = data.clone();
+  if let Data::Struct(DataStruct { fields, .. }) = &mut data {
+    for field in fields.iter_mut() {
+      let ty = &field.ty;
+      field.ty = parse_quote!(::rkyv::Archived<#ty>);
+    }
+  }
+  data
+}
+
+/* Get the name of the `rkyv` archived form of the type named `ident`. */
+fn archived_ident(ident: &Ident) -> Ident { format_ident!("Archived{}", ident) }
+
+/* If the type is marked `#[ct(zeroize)]`, a statement zeroizing the temporary buffer `buf` in
+ * generated code, so that no copies of secret data are left behind once it is no longer needed. */
+fn zeroize_temporary(zeroize: bool, buf: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  if zeroize {
+    quote! { ::zeroize::Zeroize::zeroize(&mut #buf); }
+  } else {
+    quote! {}
+  }
+}
+
+/* Push a loop comparing every pair of elements of the sequences `a` and `b`, up to the shorter
+ * length, onto `cmp`. */
+fn compare_elems(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  elem: &Type,
+  attrs: &FieldAttrs,
+  local: &Ident,
+  cmp: &mut Comparison,
+) {
+  let (x, y) = (format_ident!("x_{}", local), format_ident!("y_{}", local));
+  let mut elems = Comparison {
+    apply: cmp.apply,
+    code_size: cmp.code_size,
+    zeroize: cmp.zeroize,
+    span: cmp.span,
+    stmts: Vec::new(),
+  };
+  split_operands(
+    &quote!((*#x)),
+    &quote!((*#y)),
+    Some(elem),
+    attrs,
+    &format_ident!("{}_elem", local),
+    &mut elems,
+  );
+  let stmts = elems.stmts;
+  cmp.stmts.push(quote! {
+    for (#x, #y) in ::core::iter::Iterator::zip((#a).iter(), (#b).iter()) { #(#stmts)* }
+  });
+}
+
+/* Get statements ORing the XOR of each pair of 8-byte words of the byte arrays `a` and `b`, and then
+ * of each pair of leftover bytes, into the `u64` named `diff`. */
+fn word_diff_stmts(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  diff: &Ident,
+) -> proc_macro2::TokenStream {
+  let word = |bytes: proc_macro2::TokenStream| {
+    quote! {
+      u64::from_ne_bytes(<[u8; 8] as ::core::convert::TryFrom<&[u8]>>::try_from(#bytes).unwrap())
+    }
+  };
+  let (x_word, y_word) = (word(quote!(x)), word(quote!(y)));
+  quote! {
+    for (x, y) in ::core::iter::Iterator::zip((#a).chunks_exact(8), (#b).chunks_exact(8)) {
+      #diff |= #x_word ^ #y_word;
+    }
+    for (x, y) in ::core::iter::Iterator::zip(
+      (#a).chunks_exact(8).remainder().iter(),
+      (#b).chunks_exact(8).remainder().iter(),
+    ) {
+      #diff |= <u64 as ::core::convert::From<u8>>::from(x ^ y);
+    }
+  }
+}
+
+/* With the `simd` feature, get an expression ORing together the XOR of each pair of bytes of the
+ * byte arrays `a` and `b` into a fixed number of lanes, then reducing the lanes into a single `u8`
+ * which is zero exactly when the arrays are equal. The fixed-width inner loop is simple enough for
+ * the compiler to vectorize. */
+fn lanes_diff(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+  quote! {{
+    let mut lanes = [0u8; 32];
+    for (x, y) in ::core::iter::Iterator::zip((#a).chunks_exact(32), (#b).chunks_exact(32)) {
+      for ((lane, x), y) in
+        ::core::iter::Iterator::zip(::core::iter::Iterator::zip(lanes.iter_mut(), x), y)
+      {
+        *lane |= x ^ y;
+      }
+    }
+    for ((lane, x), y) in ::core::iter::Iterator::zip(
+      ::core::iter::Iterator::zip(lanes.iter_mut(), (#a).chunks_exact(32).remainder()),
+      (#b).chunks_exact(32).remainder(),
+    ) {
+      *lane |= x ^ y;
+    }
+    ::core::iter::Iterator::fold(lanes.iter(), 0u8, |acc, lane| acc | lane)
+  }}
+}
+
+/* Push the statements comparing the byte arrays `a` and `b` as `u64` words onto `cmp`. Equality
+ * ORs together the XOR of every pair of words, while ordering compares big-endian words, which
+ * are ordered the same way as the bytes they contain. */
+fn compare_words(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  attrs: &FieldAttrs,
+  local: &Ident,
+  cmp: &mut Comparison,
+) {
+  if let "apply_eq" | "apply_ne" = cmp.apply {
+    let diff = format_ident!("words_{}", local);
+    let stmts = word_diff_stmts(a, b, &diff);
+    cmp.stmts.push(quote! {
+      let mut #diff = 0u64;
+      #stmts
+    });
+    cmp.compare(&diff, quote!(0u64));
+  } else {
+    let (x, y) = (format_ident!("x_{}", local), format_ident!("y_{}", local));
+    let mut words = Comparison {
+      apply: cmp.apply,
+      code_size: cmp.code_size,
+      zeroize: cmp.zeroize,
+      span: cmp.span,
+      stmts: Vec::new(),
+    };
+    let word = |bytes: &Ident| {
+      quote! {
+        u64::from_be_bytes(
+          <[u8; 8] as ::core::convert::TryFrom<&[u8]>>::try_from(#bytes).unwrap(),
+        )
+      }
+    };
+    words.compare(word(&x), word(&y));
+    let stmts = words.stmts;
+    cmp.stmts.push(quote! {
+      for (#x, #y) in ::core::iter::Iterator::zip((#a).chunks_exact(8), (#b).chunks_exact(8)) {
+        #(#stmts)*
+      }
+    });
+    compare_elems(
+      &quote!((#a).chunks_exact(8).remainder()),
+      &quote!((#b).chunks_exact(8).remainder()),
+      &parse_quote!(u8),
+      attrs,
+      local,
+      cmp,
+    );
+  }
+}
+
+/* The address types from `core::net`, and `uuid::Uuid`, which are compared by their canonical
+ * integer representations. */
+const NET_TYPES: &[&str] = &[
+  "Ipv4Addr",
+  "Ipv6Addr",
+  "IpAddr",
+  "SocketAddrV4",
+  "SocketAddrV6",
+  "SocketAddr",
+  "Uuid",
+];
+
+/* Push the statements comparing `a` and `b`, of the type in NET_TYPES with the given `name`, onto
+ * `cmp`. */
+fn split_net_operands(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  name: &str,
+  attrs: &FieldAttrs,
+  local: &Ident,
+  cmp: &mut Comparison,
+) {
+  /* Compare `convert` applied to each operand, as the type `into`. */
+  let mut split = |convert: &dyn Fn(&proc_macro2::TokenStream) -> proc_macro2::TokenStream,
+                   into: Type| {
+    split_operands(&convert(a), &convert(b), Some(&into), attrs, local, cmp)
+  };
+  match name {
+    "Ipv4Addr" => split(
+      &|x| quote!(<u32 as ::core::convert::From<::core::net::Ipv4Addr>>::from(#x)),
+      parse_quote!(u32),
+    ),
+    "Ipv6Addr" => split(
+      &|x| quote!(<u128 as ::core::convert::From<::core::net::Ipv6Addr>>::from(#x)),
+      parse_quote!(u128),
+    ),
+    "IpAddr" => {
+      /* Order IPv4 addresses first, as `IpAddr` does, then compare IPv4 addresses by their
+       * IPv4-mapped IPv6 addresses. */
+      split(&|x| quote!((#x).is_ipv6()), parse_quote!(bool));
+      split(
+        &|x| {
+          quote! {
+            match #x {
+              ::core::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+              ::core::net::IpAddr::V6(ip) => ip,
+            }
+          }
+        },
+        parse_quote!(Ipv6Addr),
+      );
+    },
+    "SocketAddrV4" => {
+      split(&|x| quote!(*(#x).ip()), parse_quote!(Ipv4Addr));
+      split(&|x| quote!((#x).port()), parse_quote!(u16));
+    },
+    "SocketAddrV6" => {
+      split(&|x| quote!(*(#x).ip()), parse_quote!(Ipv6Addr));
+      split(&|x| quote!((#x).port()), parse_quote!(u16));
+      split(&|x| quote!((#x).flowinfo()), parse_quote!(u32));
+      split(&|x| quote!((#x).scope_id()), parse_quote!(u32));
+    },
+    "SocketAddr" => {
+      split(&|x| quote!((#x).is_ipv6()), parse_quote!(bool));
+      split(&|x| quote!((#x).ip()), parse_quote!(IpAddr));
+      split(&|x| quote!((#x).port()), parse_quote!(u16));
+    },
+    "Uuid" => split(&|x| quote!((#x).as_u128()), parse_quote!(u128)),
+    _ => unreachable!("{} is not in NET_TYPES", name),
+  }
+}
+
+/* Push the statements comparing `a` and `b` of type `ty` onto `cmp`. Types which don't implement
+ * the subtle traits themselves are split into operands which do. `local` is used to name any new
+ * locals. */
+fn split_operands(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  ty: Option<&Type>,
+  attrs: &FieldAttrs,
+  local: &Ident,
+  cmp: &mut Comparison,
+) {
+  if let Some(int) = ty.and_then(option_inner).and_then(nonzero_inner) {
+    /* `NonZero*` has no default value, but `None` can be substituted with zero instead, which is
+     * also how it is represented in memory. */
+    cmp.compare(
+      quote!(<u8 as ::core::convert::From<bool>>::from(#a.is_some())),
+      quote!(<u8 as ::core::convert::From<bool>>::from(#b.is_some())),
+    );
+    split_operands(
+      &quote!((#a).map_or(0, |x| x.get())),
+      &quote!((#b).map_or(0, |x| x.get())),
+      Some(&int),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if let Some(inner) = ty.and_then(option_inner) {
+    /* Compare whether each value is present first, then compare the payloads, substituting a
+     * default value for `None` so the same work is done either way. */
+    let none = format_ident!("none_{}", local);
+    cmp.stmts.push(quote! {
+      let #none = <#inner as ::core::default::Default>::default();
+    });
+    cmp.compare(
+      quote!(<u8 as ::core::convert::From<bool>>::from(#a.is_some())),
+      quote!(<u8 as ::core::convert::From<bool>>::from(#b.is_some())),
+    );
+    split_operands(
+      &quote!((*#a.as_ref().unwrap_or(&#none))),
+      &quote!((*#b.as_ref().unwrap_or(&#none))),
+      Some(inner),
+      attrs,
+      &format_ident!("{}_inner", local),
+      cmp,
+    );
+  } else if let Some(inner) = ty.and_then(|ty| generic_inner(ty, "CtOption")) {
+    /* As with `Option`, but a `CtOption` always holds a value, which is masked with a default
+     * value when it isn't present. */
+    let unwrapped = |value: &proc_macro2::TokenStream| quote!((#value).unwrap_or(<#inner as ::core::default::Default>::default()));
+    cmp.compare(
+      quote!((#a).is_some().unwrap_u8()),
+      quote!((#b).is_some().unwrap_u8()),
+    );
+    split_operands(
+      &unwrapped(a),
+      &unwrapped(b),
+      Some(inner),
+      attrs,
+      &format_ident!("{}_inner", local),
+      cmp,
+    );
+  } else if let Some(inner) =
+    ty.and_then(|ty| generic_inner(ty, "Wrapping").or_else(|| generic_inner(ty, "Saturating")))
+  {
+    /* Compare the wrapped values. */
+    split_operands(
+      &quote!((#a).0),
+      &quote!((#b).0),
+      Some(inner),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if ty.map_or(false, is_duration) {
+    /* Compare the whole seconds first, for the correct ordering. */
+    cmp.compare(quote!((#a).as_secs()), quote!((#b).as_secs()));
+    cmp.compare(quote!((#a).subsec_nanos()), quote!((#b).subsec_nanos()));
+  } else if let Some(inner) = ty.and_then(nonzero_inner) {
+    /* Compare the underlying integers. */
+    split_operands(
+      &quote!((#a).get()),
+      &quote!((#b).get()),
+      Some(&inner),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if attrs.words && matches!(ty, Some(Type::Array(TypeArray { elem, .. })) if is_u8(elem)) {
+    /* Compare 8 bytes at a time, rather than one at a time. */
+    compare_words(a, b, attrs, local, cmp);
+  } else if let (Some(Type::Array(TypeArray { elem, .. })), "apply_eq" | "apply_ne") =
+    (ty, cmp.apply)
+  {
+    if cmp.code_size && is_u8(elem) {
+      /* Call the same out-of-line comparison for every byte array. */
+      cmp.compare(
+        quote!(::subtle_derive_runtime::bytes_ct_eq(&(#a)[..], &(#b)[..]).unwrap_u8()),
+        quote!(1u8),
+      );
+    } else if cfg!(feature = "simd") && is_u8(elem) {
+      cmp.compare(lanes_diff(a, b), quote!(0u8));
+    } else if is_primitive_int(elem) {
+      /* Compare arrays of integers, such as big integer limbs, with a single slice comparison,
+       * which is 1 exactly when they are equal. */
+      cmp.compare(
+        quote!(::subtle::ConstantTimeEq::ct_eq(&(#a)[..], &(#b)[..]).unwrap_u8()),
+        quote!(1u8),
+      );
+    } else {
+      /* Compare other elements one by one, as for ordering, so that they are converted as
+       * needed and nested arrays are compared too. */
+      compare_elems(a, b, elem, attrs, local, cmp);
+    }
+  } else if let Some(Type::Array(TypeArray { elem, .. })) = ty {
+    if cmp.code_size && is_u8(elem) {
+      /* Order byte arrays with the same out-of-line comparison, by whether each is greater than
+       * the other, which are only equal when the arrays are equal. */
+      let (gt, lt) = (format_ident!("gt_{}", local), format_ident!("lt_{}", local));
+      cmp.stmts.push(quote! {
+        let (#gt, #lt) = ::subtle_derive_runtime::bytes_ct_cmp(&(#a)[..], &(#b)[..]);
+      });
+      cmp.compare(quote!(#gt.unwrap_u8()), quote!(#lt.unwrap_u8()));
+    } else {
+      /* Order arrays lexicographically, as they have the same length. */
+      compare_elems(a, b, elem, attrs, local, cmp);
+    }
+  } else if let Some(inner) = ty.and_then(|ty| generic_inner(ty, "Archived")) {
+    /* Convert archived primitives back to their native byte order, and compare anything else in
+     * its archived form. */
+    let native = ARCHIVED_NATIVE
+      .iter()
+      .any(|name| is_named_type(inner, name));
+    if native {
+      split_operands(
+        &quote!((#a).to_native()),
+        &quote!((#b).to_native()),
+        Some(inner),
+        attrs,
+        local,
+        cmp,
+      );
+    } else if is_bool(inner) || is_u8(inner) || is_named_type(inner, "i8") {
+      split_operands(a, b, Some(inner), attrs, local, cmp);
+    } else {
+      cmp.compare(a, b);
+    }
+  } else if let Some(Type::Reference(TypeReference { elem, .. })) = ty {
+    /* Compare the referents. */
+    split_operands(
+      &quote!((*#a)),
+      &quote!((*#b)),
+      Some(elem),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if let Some(Type::Tuple(TypeTuple { elems, .. })) = ty {
+    /* `subtle` has no impls for tuples, so compare each element in order, which also orders the
+     * tuples lexicographically. */
+    for (i, elem) in elems.iter().enumerate() {
+      let index = Index::from(i);
+      split_operands(
+        &quote!((#a).#index),
+        &quote!((#b).#index),
+        Some(elem),
+        attrs,
+        &format_ident!("{}_{}", local, i),
+        cmp,
+      );
+    }
+  } else if let Some(inner) = ty.and_then(secret_box_inner) {
+    /* Compare the exposed secrets. */
+    split_operands(
+      &quote!((*::secrecy::ExposeSecret::expose_secret(&#a))),
+      &quote!((*::secrecy::ExposeSecret::expose_secret(&#b))),
+      Some(&inner),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if let Some(inner) = ty.and_then(cow_inner) {
+    /* Compare the borrowed or owned contents alike. */
+    split_operands(
+      &quote!((*#a)),
+      &quote!((*#b)),
+      Some(inner),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if ty.map_or(false, |ty| {
+    is_named_type(ty, "String") || is_named_type(ty, "str")
+  }) {
+    /* Compare the UTF-8 encodings. */
+    split_operands(
+      &quote!((#a).as_bytes()),
+      &quote!((#b).as_bytes()),
+      Some(&parse_quote!([u8])),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if let Some(name) = ty.and_then(|ty| {
+    NET_TYPES
+      .iter()
+      .find(|name| is_path_ending_in(ty, name) && (**name != "Uuid" || cfg!(feature = "uuid")))
+  }) {
+    split_net_operands(a, b, name, attrs, local, cmp);
+  } else if ty.map_or(false, is_bytes_buf) {
+    /* Compare the buffer contents. */
+    split_operands(
+      &quote!((*#a)),
+      &quote!((*#b)),
+      Some(&parse_quote!([u8])),
+      attrs,
+      local,
+      cmp,
+    );
+  } else if attrs.array_vec {
+    let (elem, cap) = match ty.and_then(|ty| sized_generic_args(ty, "ArrayVec")) {
+      Some(args) => args,
+      None => Code::UnsupportedFieldType
+        .panic("#[ct(array_vec)] requires a field of type arrayvec::ArrayVec<T, CAP>"),
+    };
+    /* Copy the stored elements over default values filling the whole capacity, so that every
+     * element is compared regardless of the lengths. The copies are hygienic, since they're in
+     * scope alongside the user's expressions. */
+    let pads = [
+      Ident::new(&format!("x_pad_{}", local), Span::mixed_site()),
+      Ident::new(&format!("y_pad_{}", local), Span::mixed_site()),
+    ];
+    for (pad, value) in pads.iter().zip([a, b]) {
+      cmp.stmts.push(quote! {
+        let mut #pad = [<#elem as ::core::default::Default>::default(); #cap];
+      });
+      cmp.stmts.push(quote! {
+        #pad[..(#value).len()].copy_from_slice(&#value);
+      });
+    }
+    cmp.compare(quote!(((#a).len() as u64)), quote!(((#b).len() as u64)));
+    let [x_pad, y_pad] = &pads;
+    let elem_attrs = FieldAttrs {
+      array_vec: false,
+      ..attrs.clone()
+    };
+    compare_elems(
+      &quote!(#x_pad),
+      &quote!(#y_pad),
+      elem,
+      &elem_attrs,
+      local,
+      cmp,
+    );
+    for pad in &pads {
+      cmp.stmts.push(zeroize_temporary(cmp.zeroize, quote!(#pad)));
+    }
+  } else if let Some(elem) = ty.and_then(fixed_array_elem) {
+    /* The lengths are part of the type, so only the elements need to be compared. */
+    compare_elems(a, b, elem, attrs, local, cmp);
+  } else if let Some(elem) = ty.and_then(sequence_elem) {
+    if !attrs.variable_length {
+      Code::VariableLength.panic(format_args!(
+        "field {} has a variable length, which can't be hidden; mark it with \
+         #[ct(variable_length)] to compare it anyway",
+        local
+      ));
+    }
+    /* Compare the lengths first, then every pair of elements up to the shorter length. The
+     * lengths can be distinguished by timing, but not the contents. */
+    cmp.compare(quote!(((#a).len() as u64)), quote!(((#b).len() as u64)));
+    compare_elems(a, b, elem, attrs, local, cmp);
+  } else if let Some(float) = attrs.float {
+    let (bits, signed) = match ty {
+      Some(ty) if is_named_type(ty, "f32") => (quote!(u32), quote!(i32)),
+      Some(ty) if is_named_type(ty, "f64") => (quote!(u64), quote!(i64)),
+      _ => Code::UnsupportedFieldType
+        .panic("#[ct(float_bits)] and #[ct(float_total_order)] require an f32 or f64 field"),
+    };
+    let convert = |value: &proc_macro2::TokenStream| match float {
+      FloatCompare::Bits => quote!((#value).to_bits()),
+      /* Flip every bit of negative values, and just the sign bit of positive values. */
+      FloatCompare::TotalOrder => quote! {
+        ((#value).to_bits() ^ ((((#value).to_bits() as #signed >> (#bits::BITS - 1)) as #bits)
+          | #signed::MIN as #bits))
+      },
+    };
+    cmp.compare(convert(a), convert(b));
+  } else if ty.map_or(false, |ty| is_named_type(ty, "u128")) {
+    /* Compare the high half first, for the correct ordering. */
+    cmp.compare(quote!(((#a) >> 64) as u64), quote!(((#b) >> 64) as u64));
+    cmp.compare(quote!((#a) as u64), quote!((#b) as u64));
+  } else if ty.map_or(false, |ty| is_named_type(ty, "i128")) {
+    /* Flip the sign bit of the high half, so that negative values are ordered first. */
+    cmp.compare(
+      quote!(((#a) as u128 >> 64) as u64 ^ (1 << 63)),
+      quote!(((#b) as u128 >> 64) as u64 ^ (1 << 63)),
+    );
+    cmp.compare(quote!((#a) as u64), quote!((#b) as u64));
+  } else if let (Some(a), Some(b)) = (
+    ty.and_then(|ty| signed_to_unsigned(ty, a)),
+    ty.and_then(|ty| signed_to_unsigned(ty, b)),
+  ) {
+    cmp.compare(a, b);
+  } else if ty.map_or(false, is_bool) {
+    /* Order `false` before `true`, as `bool` does. */
+    cmp.compare(
+      quote!(<u8 as ::core::convert::From<bool>>::from(#a)),
+      quote!(<u8 as ::core::convert::From<bool>>::from(#b)),
+    );
+  } else if ty.map_or(false, |ty| is_named_type(ty, "char")) {
+    /* Order by code point, as `char` does. */
+    cmp.compare(
+      quote!(<u32 as ::core::convert::From<char>>::from(#a)),
+      quote!(<u32 as ::core::convert::From<char>>::from(#b)),
+    );
+  } else {
+    cmp.compare(a, b);
+  }
+}
+
+/* Generate the statements applying one field to an iterated comparison. */
+fn apply_stmts(
+  name: &FieldPath,
+  field: Option<&Field>,
+  apply: &str,
+  container: &ContainerAttrs,
+) -> Vec<proc_macro2::TokenStream> {
+  /* The fields of a flattened field are compared as generic values, since their types are
+   * unknown. */
+  let ty = field
+    .map(|field| &field.ty)
+    .filter(|_| !name.is_flattened());
+  let attrs = match field {
+    Some(field) if !name.is_flattened() => field_attrs(&field.attrs),
+    _ => FieldAttrs::default(),
+  };
+  if attrs.words
+    && !matches!(field, Some(Field { ty: Type::Array(TypeArray { elem, .. }), .. }) if is_u8(elem))
+  {
+    Code::UnsupportedFieldType.panic("#[ct(words)] requires a byte array field");
+  }
+  let mut cmp = Comparison {
+    apply,
+    code_size: container.code_size,
+    zeroize: container.zeroize,
+    span: field.map_or_else(Span::call_site, |field| field.ty.span()),
+    stmts: Vec::new(),
+  };
+  split_operands(
+    &quote!(self.#name),
+    &quote!(other.#name),
+    ty,
+    &attrs,
+    &name.local(),
+    &mut cmp,
+  );
+  cfg_guarded(field, cmp.stmts)
+}
+
+/* Generate the statements applying every field to an iterated comparison. With `#[ct(chunked)]`, runs
+ * of consecutive fields with the same type and no `#[ct(...)]` or `#[cfg(...)]` attributes are compared within a single
+ * loop over pairs of references to them, so that the comparison is only expanded once per run. */
+fn fields_stmts(
+  fields: &[(FieldPath, Option<&Field>)],
+  apply: &str,
+  attrs: &ContainerAttrs,
+) -> Vec<proc_macro2::TokenStream> {
+  let mut stmts = Vec::new();
+  let mut rest = fields;
+  while let Some(((name, field), _)) = rest.split_first() {
+    let chunk_type = |field: &Option<&Field>| {
+      field
+        .filter(|field| {
+          attrs.chunked
+            && !field
+              .attrs
+              .iter()
+              .any(|attr| attr.path.is_ident("ct") || attr.path.is_ident("cfg"))
+        })
+        .map(|field| &field.ty)
+    };
+    let run = match chunk_type(field) {
+      Some(ty) => rest
+        .iter()
+        .take_while(|(_, other)| chunk_type(other).map_or(false, |other| other == ty))
+        .count(),
+      None => 1,
+    };
+    if run < 2 {
+      stmts.extend(apply_stmts(name, *field, apply, attrs));
+    } else {
+      let local = format_ident!("chunk_{}", name.local());
+      let (x, y) = (format_ident!("x_{}", local), format_ident!("y_{}", local));
+      let mut cmp = Comparison {
+        apply,
+        code_size: attrs.code_size,
+        zeroize: attrs.zeroize,
+        span: field.map_or_else(Span::call_site, |field| field.ty.span()),
+        stmts: Vec::new(),
+      };
+      split_operands(
+        &quote!((*#x)),
+        &quote!((*#y)),
+        chunk_type(field),
+        &FieldAttrs::default(),
+        &local,
+        &mut cmp,
+      );
+      let names = rest[..run].iter().map(|(name, _)| name);
+      let body = cmp.stmts;
+      stmts.push(quote! {
+        for (#x, #y) in [#((&self.#names, &other.#names)),*] { #(#body)* }
+      });
+    }
+    rest = &rest[run..];
+  }
+  stmts
+}
+
+/* Generate a method body which folds every pair of fields into an `::subtle::Iterated*` value
+ * with its `apply` method, then extracts the result. With the `upstream-subtle` feature, the
+ * fields are folded into a `Choice` directly instead. Fields are `None` when their types are
+ * generic parameters, which are compared directly. */
+fn iterated_block(
+  fields: &[(FieldPath, Option<&Field>)],
+  iterated: &str,
+  apply: &str,
+  attrs: &ContainerAttrs,
+) -> proc_macro2::TokenStream {
+  let ordering = apply == "apply_gt" || apply == "apply_lt";
+  let stmts = fields_stmts(fields, apply, attrs);
+  /* Use a hygienic name for the accumulator, so it can't shadow anything in the field types. */
+  let ret = Ident::new("ret", Span::mixed_site());
+  if cfg!(feature = "upstream-subtle") {
+    /* Fold the fields into a `Choice` directly, which starts out true only for equality. */
+    let init = u8::from(apply == "apply_eq");
+    let eq = if ordering {
+      let eq = Ident::new("eq", Span::mixed_site());
+      quote! {
+        #[allow(unused_mut)]
+        let mut #eq = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8);
+      }
+    } else {
+      quote! {}
+    };
+    quote! {{
+      #[allow(unused_mut)]
+      let mut #ret = <::subtle::Choice as ::core::convert::From<u8>>::from(#init);
+      #eq
+      #(#stmts)*
+      #ret
+    }}
+  } else {
+    let iterated = format_ident!("{}", iterated);
+    quote! {{
+      let mut #ret = <::subtle::#iterated as ::subtle::IteratedOperation>::initiate();
+      #(#stmts)*
+      ::subtle::IteratedOperation::extract_result(#ret)
+    }}
+  }
+}
+
+/* Generate a method body which computes the comparison of each field into a fixed-size array of
+ * `Choice`s, then folds the array in a single loop. Ordering comparisons also collect whether each
+ * pair of fields is equal, and fold from the last field to the first, so that the first unequal
+ * pair decides the result. */
+fn choice_array_block(
+  fields: &[(FieldPath, Option<&Field>)],
+  iterated: &str,
+  apply: &str,
+  attrs: &ContainerAttrs,
+) -> proc_macro2::TokenStream {
+  /* Fields with `#[cfg(...)]` attributes leave out their elements when they're compiled out, so
+   * the arrays are only borrowed as slices of whatever length they end up with. */
+  let choices = |iterated: &str, apply: &str| {
+    let blocks = fields.iter().enumerate().map(|(i, (_, field))| {
+      let cfgs = cfg_attrs(*field);
+      let block = iterated_block(&fields[i..=i], iterated, apply, attrs);
+      quote! { #(#cfgs)* #block }
+    });
+    quote! { &[#(#blocks),*] }
+  };
+  let compared = choices(iterated, apply);
+  let ret = Ident::new("ret", Span::mixed_site());
+  match apply {
+    "apply_eq" | "apply_ne" => {
+      let (init, fold) = if apply == "apply_eq" {
+        (1u8, quote!(&=))
+      } else {
+        (0u8, quote!(|=))
+      };
+      quote! {{
+        let choices: &[::subtle::Choice] = #compared;
+        let mut #ret = <::subtle::Choice as ::core::convert::From<u8>>::from(#init);
+        for choice in choices {
+          #ret #fold *choice;
+        }
+        #ret
+      }}
+    },
+    _ => {
+      let equal = choices("IteratedEq", "apply_eq");
+      quote! {{
+        let choices: &[::subtle::Choice] = #compared;
+        let equal: &[::subtle::Choice] = #equal;
+        let mut #ret = <::subtle::Choice as ::core::convert::From<u8>>::from(0u8);
+        for (choice, eq) in
+          ::core::iter::Iterator::rev(::core::iter::Iterator::zip(choices.iter(), equal))
+        {
+          #ret = *choice | (*eq & #ret);
+        }
+        #ret
+      }}
+    },
+  }
+}
+
+/* Generate a method body comparing every field as requested by the container attributes: with
+ * choice_array_block() for `#[ct(choice_array)]`, or else with iterated_block(). */
+fn fields_block(
+  fields: &[(FieldPath, Option<&Field>)],
+  iterated: &str,
+  apply: &str,
+  attrs: &ContainerAttrs,
+) -> proc_macro2::TokenStream {
+  if attrs.choice_array {
+    choice_array_block(fields, iterated, apply, attrs)
+  } else {
+    iterated_block(fields, iterated, apply, attrs)
+  }
+}
+
+/* Add `bound` to every type parameter of `generics`, as the builtin derives do, so that a generated
+ * impl only applies when every type parameter implements the derived trait. */
+fn bounded_generics(generics: &Generics, bound: proc_macro2::TokenStream) -> Generics {
+  let mut generics = generics.clone();
+  for param in generics.type_params_mut() {
+    param.bounds.push(parse_quote!(#bound));
+  }
+  generics
+}
+
+/* Require the type itself to implement `bounds` in the where clause of `generics`, for an inherent
+ * impl whose methods call those traits on `Self`. A generic type then only gets the methods for
+ * the type parameters where the traits are implemented. */
+fn self_bounded_generics(
+  ident: &Ident,
+  generics: &Generics,
+  bounds: proc_macro2::TokenStream,
+) -> Generics {
+  let mut generics = generics.clone();
+  let (_, ty_generics, _) = generics.split_for_impl();
+  let bounded: WherePredicate = parse_quote!(#ident #ty_generics: #bounds);
+  generics.make_where_clause().predicates.push(bounded);
+  generics
+}
+
+/* If a field is marked `#[ct(max_depth = N)]`, get it along with `N`, after checking that it links
+ * to another node of the same type. */
+fn depth_bounded_link(ident: &Ident, generics: &Generics, data: &Data) -> Option<(Member, usize)> {
+  let mut links = struct_fields(data)
+    .into_iter()
+    .filter_map(|(name, field)| Some((name, field, field_attrs(&field.attrs).max_depth?)));
+  let (name, field, depth) = links.next()?;
+  if links.next().is_some() {
+    Code::ConflictingOptions.panic("only one field can be marked #[ct(max_depth = N)]");
+  }
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("#[ct(max_depth = N)] can't be used on generic types");
+  }
+  let name = ident.unraw().to_string();
+  let is_self = |ty: &Type| is_named_type(ty, "Self") || is_named_type(ty, &name);
+  let boxed = generic_inner(&field.ty, "Option").and_then(|ty| generic_inner(ty, "Box"));
+  if !boxed.map_or(false, is_self) {
+    Code::UnsupportedFieldType
+      .panic("#[ct(max_depth = N)] requires a field of type Option<Box<Self>>");
+  }
+  Some((name, depth))
+}
+
+/* Generate a method body which compares the lists of nodes starting at `self` and `other`, which
+ * are linked by the field `link`. Exactly `depth` pairs of nodes are always compared, with a
+ * default-constructed dummy node standing in for the nodes past the end of each list, so the time
+ * taken doesn't depend on the lengths of the lists. */
+fn bounded_depth_block(
+  ident: &Ident,
+  fields: &[(FieldPath, Option<&Field>)],
+  link: &Member,
+  depth: usize,
+  attrs: &ContainerAttrs,
+) -> proc_macro2::TokenStream {
+  let node_fields: Vec<(FieldPath, Option<&Field>)> = fields
+    .iter()
+    .filter(|(name, _)| name.0[0] != *link)
+    .cloned()
+    .collect();
+  let node_block = fields_block(&node_fields, "IteratedEq", "apply_eq", attrs);
+  let [ret, dummy, a, b, x, y] =
+    ["ret", "dummy", "a", "b", "x", "y"].map(|name| Ident::new(name, Span::mixed_site()));
+  quote! {{
+    /* Compare the fields of a single node, other than the link to the next one. */
+    trait ShallowCtEq {
+      fn shallow_ct_eq(&self, other: &Self) -> ::subtle::Choice;
+    }
+    impl ShallowCtEq for #ident {
+      #[inline]
+      fn shallow_ct_eq(&self, other: &Self) -> ::subtle::Choice #node_block
+    }
+    let #dummy = <Self as ::core::default::Default>::default();
+    let mut #ret = <Self as ShallowCtEq>::shallow_ct_eq(self, other);
+    let (mut #a, mut #b) = (&self.#link, &other.#link);
+    #[allow(clippy::reversed_empty_ranges)]
+    for _ in 1..#depth {
+      let #x: &Self =
+        ::core::option::Option::unwrap_or(::core::option::Option::as_deref(#a), &#dummy);
+      let #y: &Self =
+        ::core::option::Option::unwrap_or(::core::option::Option::as_deref(#b), &#dummy);
+      #ret &= <u8 as ::subtle::ConstantTimeEq>::ct_eq(
+        &<u8 as ::core::convert::From<bool>>::from(::core::option::Option::is_some(#a)),
+        &<u8 as ::core::convert::From<bool>>::from(::core::option::Option::is_some(#b)),
+      ) & <Self as ShallowCtEq>::shallow_ct_eq(#x, #y);
+      #a = &#x.#link;
+      #b = &#y.#link;
+    }
+    /* A list with more than `depth` nodes can't be compared in full, so it never compares equal. */
+    #ret &= ::subtle::Choice::from(<u8 as ::core::convert::From<bool>>::from(
+      ::core::option::Option::is_none(#a) & ::core::option::Option::is_none(#b),
+    ));
+    #ret
+  }}
+}
+
+/* Generate a method body which compares the bytes of `self` and `other` with a single slice
+ * comparison, after checking at compile time that the type has no padding bytes. */
+fn pod_bytes_block(ident: &Ident, generics: &Generics, data: &Data) -> proc_macro2::TokenStream {
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("#[ct(pod_bytes)] can't be used on generic types");
+  }
+  /* Only count the fields which are compiled in. */
+  let sizes = struct_fields(data).into_iter().map(|(_, field)| {
+    let cfgs = cfg_attrs(Some(field));
+    let ty = &field.ty;
+    quote! { #(#cfgs)* { size += ::core::mem::size_of::<#ty>(); } }
+  });
+  quote! {{
+    /* The fields only fill the whole type if there is no padding between them. */
+    const _: () = ::core::assert!(
+      ::core::mem::size_of::<#ident>() == {
+        #[allow(unused_mut)]
+        let mut size = 0;
+        #(#sizes)*
+        size
+      },
+      "#[ct(pod_bytes)] requires a type without padding"
+    );
+    ::subtle::ConstantTimeEq::ct_eq(::bytemuck::bytes_of(self), ::bytemuck::bytes_of(other))
+  }}
+}
+
+/* Get an expression for the differing bits of the integers `a` and `b` of type `ty`, folded into a
+ * `u64` which is zero exactly when they are equal. */
+fn xor_diff(
+  a: &proc_macro2::TokenStream,
+  b: &proc_macro2::TokenStream,
+  ty: &Type,
+) -> Option<proc_macro2::TokenStream> {
+  if !is_primitive_int(ty) {
+    None
+  } else if is_named_type(ty, "u128") || is_named_type(ty, "i128") {
+    Some(quote! {{
+      let diff = (#a) ^ (#b);
+      (diff as u64) | ((diff >> 64) as u64)
+    }})
+  } else {
+    Some(quote! { (((#a) ^ (#b)) as u64) })
+  }
+}
+
+/* If every field is a primitive integer or an array of them, generate a method body which ORs the
+ * XOR of every pair of fields into a single accumulator, and only converts it into a `Choice` at
+ * the end. */
+fn xor_block(fields: &[(FieldPath, Option<&Field>)]) -> Option<proc_macro2::TokenStream> {
+  let acc = Ident::new("acc", Span::mixed_site());
+  let stmts = fields
+    .iter()
+    .map(|(name, field)| {
+      let (a, b) = (quote!(self.#name), quote!(other.#name));
+      if name.is_flattened() {
+        return None;
+      }
+      match &field?.ty {
+        Type::Array(TypeArray { elem, .. }) if is_u8(elem) && field_attrs(&field?.attrs).words => {
+          Some(word_diff_stmts(&a, &b, &acc))
+        },
+        Type::Array(TypeArray { elem, .. }) if is_u8(elem) && cfg!(feature = "simd") => {
+          let diff = lanes_diff(&a, &b);
+          Some(quote! { #acc |= <u64 as ::core::convert::From<u8>>::from(#diff); })
+        },
+        Type::Array(TypeArray { elem, .. }) if is_u8(elem) => Some(quote! {
+          #acc |= <u64 as ::core::convert::From<u8>>::from(
+            1 ^ ::subtle::ConstantTimeEq::ct_eq(&#a[..], &#b[..]).unwrap_u8(),
+          );
+        }),
+        Type::Array(TypeArray { elem, .. }) => {
+          let diff = xor_diff(&quote!(*x), &quote!(*y), elem)?;
+          Some(quote! {
+            for (x, y) in ::core::iter::Iterator::zip(#a.iter(), #b.iter()) { #acc |= #diff; }
+          })
+        },
+        ty => {
+          let diff = xor_diff(&a, &b, ty)?;
+          Some(quote! { #acc |= #diff; })
+        },
+      }
+      .map(|stmt| {
+        let cfgs = cfg_attrs(*field);
+        quote! { #(#cfgs)* { #stmt } }
+      })
+    })
+    .collect::<Option<Vec<proc_macro2::TokenStream>>>()?;
+  Some(quote! {{
+    #[allow(unused_mut)]
+    let mut #acc = 0u64;
+    #(#stmts)*
+    ::subtle::ConstantTimeEq::ct_eq(&#acc, &0u64)
+  }})
+}
+
+/* Allow the lints which generated code can trip in crates that deny them, since the casts,
+ * indexing and fully qualified paths it uses are deliberate. */
+fn allowed_lints() -> proc_macro2::TokenStream {
+  quote! {
+    #[allow(
+      trivial_numeric_casts,
+      unreachable_pub,
+      unused_qualifications,
+      unused_results,
+      clippy::arithmetic_side_effects,
+      clippy::as_conversions,
+      clippy::cast_lossless,
+      clippy::cast_possible_truncation,
+      clippy::cast_possible_wrap,
+      clippy::cast_precision_loss,
+      clippy::cast_sign_loss,
+      clippy::default_trait_access,
+      clippy::indexing_slicing,
+      clippy::inline_always,
+      clippy::many_single_char_names,
+      clippy::missing_panics_doc,
+      clippy::redundant_closure_for_method_calls,
+      clippy::semicolon_if_nothing_returned,
+      clippy::similar_names,
+      clippy::trivially_copy_pass_by_ref,
+      clippy::unwrap_used
+    )]
+  }
+}
+
+/* Get the attributes for every generated trait impl, which marks it as derived so that lints
+ * skipping the output of builtin derives skip it too. */
+fn derived_attrs() -> proc_macro2::TokenStream {
+  let lints = allowed_lints();
+  quote! {
+    #[automatically_derived]
+    #lints
+  }
+}
+
+/* Get the attribute selected by `#[ct(inline = "...")]` for generated trait methods. */
+fn inline_attr(attrs: &ContainerAttrs) -> proc_macro2::TokenStream {
+  match attrs.inline {
+    Inline::Hint => quote! { #[inline] },
+    Inline::Always => quote! { #[inline(always)] },
+    Inline::Never => quote! { #[inline(never)] },
+    Inline::Default => quote! {},
+  }
+}
+
+/* Wrap a method body producing a `Choice` so that its result passes through
+ * `core::hint::black_box()`, if requested with `#[ct(opt_barrier)]`. */
+fn with_barrier(body: impl quote::ToTokens, attrs: &ContainerAttrs) -> proc_macro2::TokenStream {
+  if attrs.opt_barrier {
+    let ret = Ident::new("ret", Span::mixed_site());
+    quote! {{
+      let #ret: ::subtle::Choice = #body;
+      ::core::hint::black_box(#ret)
+    }}
+  } else {
+    quote! { #body }
+  }
+}
+
+/* Generate statements clearing `expected` unless every field of `a` equals the same field of `b`
+ * with `==`, comparing the bits of floats instead. */
+fn field_eq_stmts(
+  data: &Data,
+  a: proc_macro2::TokenStream,
+  b: proc_macro2::TokenStream,
+  expected: &Ident,
+) -> Vec<proc_macro2::TokenStream> {
+  compared_fields(data)
+    .into_iter()
+    .map(|(member, field)| {
+      let cfgs = cfg_attrs(field);
+      let float = field
+        .filter(|_| !member.is_flattened())
+        .and_then(|field| field_attrs(&field.attrs).float);
+      let eq = match float {
+        Some(_) => quote!((#a.#member).to_bits() == (#b.#member).to_bits()),
+        None => quote!(#a.#member == #b.#member),
+      };
+      quote! { #(#cfgs)* { #expected &= #eq; } }
+    })
+    .collect()
+}
+
+/* With `#[ct(debug_check)]`, wrap the body of a comparison method so that debug builds assert that
+ * its result is `expect` when every field compares equal with `==`, and its negation otherwise. */
+fn debug_checked(
+  body: proc_macro2::TokenStream,
+  data: &Data,
+  attrs: &ContainerAttrs,
+  method: &str,
+  expect: bool,
+) -> proc_macro2::TokenStream {
+  if !attrs.debug_check {
+    return body;
+  }
+  let ret = Ident::new("ret", Span::mixed_site());
+  let expected = Ident::new("expected", Span::mixed_site());
+  let checks = field_eq_stmts(data, quote!(self), quote!(other), &expected);
+  let message = format!("{}() disagrees with comparing each field with ==", method);
+  let expected_result = if expect {
+    quote!(#expected)
+  } else {
+    quote!(!#expected)
+  };
+  quote! {
+    let #ret: ::subtle::Choice = { #body };
+    /* Only check the result in debug builds, so that release timing is unaffected. */
+    #[cfg(debug_assertions)]
+    {
+      #[allow(unused_mut)]
+      let mut #expected = true;
+      #(#checks)*
+      ::core::debug_assert_eq!(
+        ::core::convert::Into::<bool>::into(#ret),
+        #expected_result,
+        #message
+      );
+    }
+    #ret
+  }
+}
+
+/* Generate a ConstantTimeEq impl which compares every field. */
+fn ct_eq_impl(
+  ident: &Ident,
+  generics: &Generics,
+  attrs: &ContainerAttrs,
+  data: &Data,
+) -> proc_macro2::TokenStream {
+  let mut generics = bounded_generics(generics, quote!(::subtle::ConstantTimeEq));
+  /* The debug checks compare each field with `==`. */
+  if attrs.debug_check {
+    let where_clause = generics.make_where_clause();
+    for (_, field) in struct_fields(data) {
+      let ty = &field.ty;
+      /* Only the fields of a flattened field are compared, and their types are unknown. */
+      if field_attrs(&field.attrs).flatten.is_none() {
+        where_clause
+          .predicates
+          .push(parse_quote!(#ty: ::core::cmp::PartialEq));
+      }
+    }
+  }
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  /* Generate the function body of a ct_eq() implementation. */
+  let fields = compared_fields(data);
+  let link = depth_bounded_link(ident, &generics, data);
+  let eq_block = if let Some((link, depth)) = &link {
+    if attrs.pod_bytes || attrs.ct_ne {
+      Code::ConflictingOptions
+        .panic("#[ct(max_depth = N)] can't be combined with #[ct(pod_bytes)] or #[ct(ct_ne)]");
+    }
+    bounded_depth_block(ident, &fields, link, *depth, attrs)
+  } else if attrs.pod_bytes {
+    pod_bytes_block(ident, &generics, data)
+  } else if let Some(block) = xor_block(&fields).filter(|_| !attrs.choice_array && !attrs.code_size)
+  {
+    block
+  } else {
+    fields_block(&fields, "IteratedEq", "apply_eq", attrs)
+  };
+  let eq_block = debug_checked(with_barrier(eq_block, attrs), data, attrs, "ct_eq", true);
+
+  let inline = inline_attr(attrs);
+
+  /* Generate a ct_ne() override directly from the fields too, if requested. */
+  let ne_method = if attrs.ct_ne && attrs.pod_bytes {
+    Code::ConflictingOptions.panic("#[ct(ct_ne)] can't be combined with #[ct(pod_bytes)]");
+  } else if attrs.ct_ne {
+    let ne_block = debug_checked(
+      with_barrier(
+        fields_block(&fields, "IteratedNe", "apply_ne", attrs),
+        attrs,
+      ),
+      data,
+      attrs,
+      "ct_ne",
+      false,
+    );
+    quote! {
+      #inline
+      fn ct_ne(&self, other: &Self) -> ::subtle::Choice {
+        #ne_block
+      }
+    }
+  } else {
+    quote! {}
+  };
+
+  /* Insert the ct_eq() block into the quoted trait method. */
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::subtle::ConstantTimeEq for #ident #ty_generics #where_clause {
+      #inline
+      fn ct_eq(&self, other: &Self) -> ::subtle::Choice {
+        #eq_block
+      }
+
+      #ne_method
+    }
+  }
+}
+
+/// Derive macro for
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html)
+/// implemented using [`subtle::IteratedEq`](https://docs.rs/subtle/latest/subtle/struct.IteratedEq.html).
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct S { x: u8, y: u8 }
+/// let s1 = S { x: 0, y: 1 };
+/// let s2 = S { x: 0, y: 2 };
+/// assert!(bool::from(s1.ct_eq(&s1)));
+/// assert!(bool::from(s2.ct_eq(&s2)));
+/// assert!(bool::from(!s1.ct_eq(&s2)));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct T(u8, u8);
+/// let t1 = T(0, 1);
+/// let t2 = T(0, 2);
+/// assert!(bool::from(t1.ct_eq(&t1)));
+/// assert!(bool::from(t2.ct_eq(&t2)));
+/// assert!(bool::from(!t1.ct_eq(&t2)));
+///```
+///
+/// The default `ct_ne()` negates `ct_eq()`. With `#[ct(ct_ne)]`, a `ct_ne()` override is also
+/// generated which folds the fields directly with
+/// [`subtle::IteratedNe`](https://docs.rs/subtle/latest/subtle/struct.IteratedNe.html).
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(ct_ne)]
+/// struct U { x: u8, y: u8, z: u8 }
+/// let u1 = U { x: 0, y: 1, z: 2 };
+/// let u2 = U { x: 0, y: 1, z: 3 };
+/// assert!(bool::from(!u1.ct_ne(&u1)));
+/// assert!(bool::from(u1.ct_ne(&u2)));
+///```
+///
+/// A `#[repr(C)]` type implementing
+/// [`bytemuck::Pod`](https://docs.rs/bytemuck/latest/bytemuck/trait.Pod.html) can be marked
+/// `#[ct(pod_bytes)]` to compare its bytes from
+/// [`bytemuck::bytes_of()`](https://docs.rs/bytemuck/latest/bytemuck/fn.bytes_of.html) with a
+/// single slice comparison instead of comparing each field. The sizes of its fields are checked at
+/// compile time to add up to the size of the type, so that no padding bytes are compared.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ConstantTimeEq)]
+/// #[repr(C)]
+/// #[ct(pod_bytes)]
+/// struct Point { x: u32, y: u32 }
+/// assert!(bool::from(Point { x: 1, y: 2 }.ct_eq(&Point { x: 1, y: 2 })));
+/// assert!(bool::from(!Point { x: 1, y: 2 }.ct_eq(&Point { x: 2, y: 1 })));
+///```
+///
+/// With the `rkyv` feature, a type deriving [`rkyv::Archive`](https://docs.rs/rkyv/latest/rkyv/trait.Archive.html)
+/// can be marked `#[ct(archived)]` to also implement the trait for its archived form, named
+/// `Archived` followed by the type's name, so that values accessed without deserializing them can
+/// be compared too. Multi-byte primitive fields are compared after converting them back from their
+/// archived byte order with `to_native()`, `u8`, `i8` and `bool` fields are compared as usual, and
+/// any other field is compared in its archived form, which must implement the trait itself (e.g.
+/// by deriving it with `#[ct(archived)]` as well). The comparison derives
+/// [`ConstantTimeGreater`](derive@ConstantTimeGreater) and
+/// [`ConstantTimeLess`](derive@ConstantTimeLess) accept this option too.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[cfg(feature = "rkyv")]
+/// {
+///   #[derive(rkyv::Archive, rkyv::Serialize, ConstantTimeEq)]
+///   #[ct(archived)]
+///   struct Grant { scope: u32, flags: [u8; 2] }
+///   let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&Grant { scope: 7, flags: [1, 2] }).unwrap();
+///   let archived = unsafe { rkyv::access_unchecked::<ArchivedGrant>(&bytes) };
+///   assert!(bool::from(archived.ct_eq(archived)));
+/// }
+///```
+///
+/// With `#[ct(chunked)]`, runs of consecutive fields with the same type and no `#[ct(...)]` options
+/// of their own are compared within a loop, which keeps the size of the expansion and the generated
+/// code down for types with very many fields, such as mirrors of C structs. The comparison derives
+/// accept this option too.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(chunked)]
+/// struct Registers { a: u32, b: u32, c: u32, flag: bool, d: Option<u16>, e: Option<u16> }
+/// let regs = Registers { a: 1, b: 2, c: 3, flag: true, d: None, e: Some(5) };
+/// assert!(bool::from(regs.ct_eq(&Registers { ..regs })));
+/// assert!(bool::from(!regs.ct_eq(&Registers { c: 4, ..regs })));
+/// assert!(bool::from(!regs.ct_eq(&Registers { e: Some(6), ..regs })));
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(chunked)]
+/// struct Record { r#type: u8, r#match: u8, r#fn: Option<u8> }
+/// let record = Record { r#type: 1, r#match: 2, r#fn: None };
+/// assert!(bool::from(record.ct_eq(&Record { r#type: 1, r#match: 2, r#fn: None })));
+/// assert!(bool::from(!record.ct_eq(&Record { r#type: 1, r#match: 3, r#fn: None })));
+///```
+///
+/// With `#[ct(choice_array)]`, the comparison of each field is first collected into a fixed-size
+/// array with one `Choice` per field, which is then folded in a single loop. This gives the
+/// optimizer a simple loop to vectorize, and makes it easy to check in the expansion that every
+/// field contributes to the result exactly once. It takes precedence over `#[ct(chunked)]`. The
+/// comparison derives accept this option too, and fold the arrays from the last field to the first.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(choice_array, ct_ne)]
+/// struct Claims { user: u64, admin: bool, expiry: Option<u32> }
+/// let claims = Claims { user: 1, admin: false, expiry: Some(60) };
+/// assert!(bool::from(claims.ct_eq(&Claims { ..claims })));
+/// assert!(bool::from(claims.ct_ne(&Claims { admin: true, ..claims })));
+/// assert!(bool::from(!claims.ct_eq(&Claims { expiry: None, ..claims })));
+///```
+///
+/// With `#[ct(code_size)]`, which requires the `runtime` feature, byte arrays are compared by
+/// calling out-of-line functions from `subtle-derive-runtime`, which are compiled once and shared
+/// by every comparison in the program, rather than expanding a comparison inline for each field.
+/// This suits embedded targets where flash is scarce. The comparison derives accept this option
+/// too, and [`ConstantTimeLess`](derive@ConstantTimeLess) then reuses the `ct_gt()` generated by
+/// [`ConstantTimeGreater`](derive@ConstantTimeGreater) with the arguments swapped, so that the
+/// field comparisons are only generated once for both orderings.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[cfg(feature = "runtime")]
+/// {
+///   use subtle::ConstantTimeLess;
+///   use subtle_derive::{ConstantTimeGreater, ConstantTimeLess};
+///   #[derive(ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess)]
+///   #[ct(code_size)]
+///   struct Firmware { version: u16, hash: [u8; 32] }
+///   let firmware = Firmware { version: 2, hash: [7; 32] };
+///   assert!(bool::from(firmware.ct_eq(&Firmware { version: 2, hash: [7; 32] })));
+///   assert!(bool::from(!firmware.ct_eq(&Firmware { version: 2, hash: [8; 32] })));
+///   assert!(bool::from(firmware.ct_lt(&Firmware { version: 2, hash: [8; 32] })));
+///   assert!(bool::from(!firmware.ct_lt(&Firmware { version: 1, hash: [8; 32] })));
+/// }
+///```
+///
+/// With `#[ct(opt_barrier)]`, the result of each comparison is passed through
+/// [`core::hint::black_box()`] before it is returned, so that the optimizer can't see how it was
+/// computed and turn its uses back into branches on the fields. The comparison derives accept this
+/// option too.
+///
+/// The generated methods are marked `#[inline]`. With `#[ct(inline = "always")]` or
+/// `#[ct(inline = "never")]` they are marked `#[inline(always)]` or `#[inline(never)]` instead,
+/// e.g. to keep comparisons out of line for more stable timing and smaller code, and with
+/// `#[ct(inline = "default")]` they are left unmarked. The comparison derives accept this option
+/// too.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(opt_barrier, ct_ne, inline = "never")]
+/// struct Tag { id: u8, ok: bool }
+/// assert!(bool::from(Tag { id: 1, ok: true }.ct_eq(&Tag { id: 1, ok: true })));
+/// assert!(bool::from(Tag { id: 1, ok: true }.ct_ne(&Tag { id: 1, ok: false })));
+///```
+///
+/// With `#[ct(debug_check)]`, the result of each generated method is also checked against
+/// comparing every field with `==`, or comparing the bits of float fields, with `debug_assert_eq!`.
+/// This catches mistakes in the derive or in the impls of the field types during development, and
+/// is compiled out without `debug_assertions`, so release timing is unaffected. Every field type
+/// must then implement [`PartialEq`].
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(debug_check, ct_ne)]
+/// struct Nonce { counter: u64, salt: [u8; 12] }
+/// let nonce = Nonce { counter: 1, salt: [7; 12] };
+/// assert!(bool::from(nonce.ct_eq(&Nonce { counter: 1, salt: [7; 12] })));
+/// assert!(bool::from(nonce.ct_ne(&Nonce { counter: 2, salt: [7; 12] })));
+///```
+///
+/// With `#[ct(kani)]`, a [Kani](https://model-checking.github.io/kani/) proof harness is also
+/// generated behind `cfg(kani)`, in a module named after the type as `<type>_ct_proof`. It checks
+/// that `ct_eq()` holds exactly when every field compares equal with `==`, or has the same bits for
+/// fields marked `#[ct(float_bits)]` or `#[ct(float_total_order)]`, for all values of the type. The
+/// type must implement `kani::Arbitrary`, so every field must have a bounded domain, as with
+/// integers and fixed-size arrays. Generic types are not supported.
+///
+/// Since the harness is behind `cfg(kani)`, which cargo doesn't know about, crates using
+/// `#[ct(kani)]` should declare it in their manifest, so that the `unexpected_cfgs` lint doesn't
+/// warn about it:
+///
+/// ```toml
+/// [lints.rust]
+/// unexpected_cfgs = { level = "warn", check-cfg = ['cfg(kani)'] }
+/// ```
+///
+/// The harness is then checked with `cargo kani`, or `cargo kani --tests` if the type is defined
+/// in a test. `kani::Arbitrary` can be derived for the type only when verifying, with
+/// `#[cfg_attr(kani, derive(kani::Arbitrary))]`.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(kani)]
+/// struct Pin { digits: [u8; 4], #[ct(float_bits)] weight: f32 }
+/// assert!(bool::from(Pin { digits: [1; 4], weight: 0.5 }.ct_eq(&Pin { digits: [1; 4], weight: 0.5 })));
+///```
+///
+/// A field marked `#[ct(flatten(a, b))]` isn't compared as a whole. Instead, the fields it names
+/// are compared directly, in the order they are listed, as if they were fields of the type itself.
+/// This allows a struct to be compared whose field types come from another crate, and don't
+/// implement the traits, as long as their fields are visible. Fields of a tuple struct are named by
+/// their indices, as in `#[ct(flatten(0, 1))]`. The types of the named fields aren't known to the
+/// derive, so they are compared with the traits as they are, without the conversions described
+/// below. The comparison derives accept this option too.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// /* A type from another crate, which doesn't implement the traits. */
+/// pub struct Version { pub major: u32, pub minor: u32 }
+///
+/// #[derive(ConstantTimeEq, subtle_derive::ConstantTimeGreater)]
+/// struct Release { #[ct(flatten(major, minor))] version: Version, build: u64 }
+/// let release = Release { version: Version { major: 1, minor: 2 }, build: 7 };
+/// let next = Release { version: Version { major: 1, minor: 3 }, build: 0 };
+/// assert!(bool::from(release.ct_eq(&Release { version: Version { major: 1, minor: 2 }, build: 7 })));
+/// assert!(bool::from(!release.ct_eq(&next)));
+/// assert!(bool::from(subtle::ConstantTimeGreater::ct_gt(&next, &release)));
+///```
+///
+/// A linked list, whose nodes link to the next one with an `Option<Box<Self>>` field, can be
+/// compared by marking that field `#[ct(max_depth = N)]`, where `N` is the maximum number of nodes
+/// in a list. `ct_eq()` then always walks `N` nodes of both lists, standing in a dummy node from
+/// `Default::default()` for each node past the end of a list, so that the time taken doesn't
+/// depend on the lengths of the lists. The type must implement [`Default`], can't be generic, and
+/// can't also use `#[ct(ct_ne)]` or `#[ct(pod_bytes)]`. `N` must be at least 1. A list of more than
+/// `N` nodes never compares equal, even to itself, rather than leaving any of its nodes out of the
+/// comparison. Since `==` must be reflexive, [`ConstEq`](derive@ConstEq) and
+/// [`Secret`](derive@Secret) can't be derived for such a type.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq, Default)]
+/// struct Node { key: [u8; 4], #[ct(max_depth = 3)] next: Option<Box<Node>> }
+/// let list = |keys: &[u8]| {
+///   keys.iter().rev().fold(None, |next, &key| Some(Box::new(Node { key: [key; 4], next })))
+/// };
+/// let (one, two) = (list(&[1]).unwrap(), list(&[1, 2]).unwrap());
+/// assert!(bool::from(two.ct_eq(&list(&[1, 2]).unwrap())));
+/// assert!(bool::from(!two.ct_eq(&one)));
+/// assert!(bool::from(!two.ct_eq(&list(&[1, 3]).unwrap())));
+/// let four = list(&[1, 2, 3, 4]).unwrap();
+/// assert!(bool::from(!four.ct_eq(&list(&[1, 2, 3, 4]).unwrap())));
+///```
+///
+/// With `#[ct(crate = "path")]`, the traits are taken from the crate at `path` instead of `::subtle`.
+/// The other derives accept this option as well.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// mod fork {
+///   pub use subtle::*;
+/// }
+/// #[derive(ConstantTimeEq)]
+/// #[ct(crate = "fork")]
+/// struct Forked(u8);
+/// assert!(bool::from(Forked(1).ct_eq(&Forked(1))));
+///```
+///
+/// Fields of some types which `subtle` doesn't implement its traits for are converted into values
+/// which it does before comparing. The comparison derives
+/// [`ConstantTimeGreater`](derive@ConstantTimeGreater) and
+/// [`ConstantTimeLess`](derive@ConstantTimeLess) convert fields the same way:
+/// - `Option<T>` is compared by whether each value is present, and then by its payload, ordering
+///   `None` before any `Some`. A `None` payload is replaced with `T::default()`, so `T` must
+///   implement [`Default`], and the same comparisons are performed whether or not each value is
+///   present.
+/// - `subtle::CtOption<T>` is compared like `Option<T>`, by whether each value is present and then
+///   by its value, which is replaced with `T::default()` when it isn't present. `T` must implement
+///   [`Default`] and
+///   [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html).
+/// - `bool` is compared as a `u8`, ordering `false` before `true`.
+/// - `char` is compared as a `u32`, ordering by code point.
+/// - `NonZeroU32` and the other `core::num::NonZero*` types are compared as the integers they
+///   contain, from `get()`. Within an `Option`, a `None` payload is replaced with zero instead of
+///   a default value.
+/// - `core::num::Wrapping<T>` and `core::num::Saturating<T>` are compared as the `T` they wrap.
+/// - `core::time::Duration` is compared as its whole seconds, and then its subsecond nanoseconds.
+/// - `Vec<T>` can only be compared when marked `#[ct(variable_length)]`, since its length can't
+///   be hidden. The lengths are compared first, and then each pair of elements up to the shorter
+///   length, with `T` converted as above. The time taken depends on the lengths, but not on the
+///   contents, so this is only constant-time for values of equal (or public) length. Vectors are
+///   ordered by length first, so a shorter vector is always less than a longer one.
+/// - `String` and `str` are likewise compared as their UTF-8 bytes when marked
+///   `#[ct(variable_length)]`.
+/// - A slice `[T]` is compared like `Vec<T>` when marked `#[ct(variable_length)]`.
+/// - A tuple such as `(u8, u64)` or `([u8; 16], u32)` is compared element by element, with each
+///   element converted as above, and ordered lexicographically.
+/// - A reference `&T` is compared as the `T` it refers to, so a borrowed slice like `&'a [u8]`
+///   is compared as a slice.
+/// - `Cow<'a, B>` is compared as the `B` it borrows or owns, so `Cow<'a, [u8]>` and
+///   `Cow<'a, str>` are compared as above whether or not the two values own their contents.
+/// - With the `heapless` feature, `heapless::Vec<T, N>` is compared like `Vec<T>` when marked
+///   `#[ct(variable_length)]`. Its length is bounded by `N`, but only the stored elements are
+///   compared, so the time taken still depends on the lengths.
+/// - With the `smallvec` feature, `smallvec::SmallVec<[T; N]>` is likewise compared like `Vec<T>`
+///   when marked `#[ct(variable_length)]`, whether or not its elements are stored inline.
+/// - With the `bytes` feature, `bytes::Bytes` and `bytes::BytesMut` are compared as their
+///   contents when marked `#[ct(variable_length)]`, like `Vec<u8>`.
+/// - With the `secrecy` feature, `secrecy::SecretBox<T>` is compared as the `T` it exposes, along
+///   with `SecretString` as a `str` and `SecretSlice<T>` as a `[T]`. The secret is only exposed
+///   within the generated comparison.
+/// - `core::net` addresses are compared as integers: `Ipv4Addr` as a `u32`, `Ipv6Addr` as a
+///   `u128`, and `IpAddr` as whether it is IPv6, then as its IPv4-mapped IPv6 address. Socket
+///   addresses are compared by their IP address and then their port, along with the flow info and
+///   scope ID of a `SocketAddrV6`. With the `uuid` feature, `uuid::Uuid` is compared as a `u128`.
+/// - An `arrayvec::ArrayVec<T, CAP>` field marked `#[ct(array_vec)]` is compared by its length, and
+///   then by every element up to its full capacity `CAP`, with the unused tail filled in by
+///   `T::default()`. Since the stored elements are copied over an array of `T::default()`, `T`
+///   must implement [`Copy`] and [`Default`] as well as the traits being derived. Apart from
+///   copying the stored elements, the time taken doesn't depend on the lengths, so no
+///   `#[ct(variable_length)]` marker is needed. If the type is marked `#[ct(zeroize)]`, the padded copies are zeroized
+///   after the comparison, so `T` must also implement `zeroize::Zeroize`.
+/// - With the `generic-array` or `hybrid-array` feature, `generic_array::GenericArray<T, N>` or
+///   `hybrid_array::Array<T, N>` respectively is compared element by element, like an array. These
+///   hold the outputs of the RustCrypto digests and MACs.
+/// - `f32` and `f64` can't be compared by default, since their `PartialEq` and `PartialOrd` impls
+///   can't be reproduced in constant time. A float field marked `#[ct(float_bits)]` is compared by
+///   its bit pattern from `to_bits()`, so `NaN` equals a `NaN` with the same bits, `0.0` does not
+///   equal `-0.0`, and ordering follows the raw bits, placing negative numbers above positive ones.
+///   With `#[ct(float_total_order)]`, floats are instead ordered by IEEE 754 `totalOrder`, as
+///   with [`f64::total_cmp()`]: `-NaN < -inf < ... < -0.0 < 0.0 < ... < inf < NaN`, and equality
+///   again requires identical bits.
+/// - `u128` and `i128` are compared as their high and low 64-bit halves.
+/// - Arrays of primitive integers, such as the `[u64; N]` limbs of a big integer or a `[u8; 32]`
+///   key, are checked for equality with a single slice comparison rather than element by element.
+///   With the `simd` feature, byte arrays are instead checked by ORing together the XOR of each
+///   pair of bytes into 32 lanes, a loop which the compiler can vectorize, before reducing the
+///   lanes to a single byte. `core::simd` isn't used, since it is still unstable.
+///   Arrays of any other type, such as arrays of a type deriving the traits itself, arrays of
+///   `bool` or `Option<T>`, and arrays of arrays, are compared element by element, with each
+///   element converted as above. Arrays are ordered lexicographically, comparing each pair of
+///   elements in turn.
+/// - A byte array marked `#[ct(words)]`, such as a large buffer of secret data, is compared 8 bytes
+///   at a time as `u64` words, along with any leftover bytes at the end. Equality ORs together the
+///   XOR of every pair of words, and ordering compares the words in big-endian order, which orders
+///   the arrays the same way as comparing each pair of bytes.
+/// - Signed integers are compared by flipping their sign bit and converting them to the unsigned
+///   type of the same width, since `subtle` only orders unsigned integers. This maps the signed
+///   range onto the unsigned range in the same order.
+///
+/// Scalar fields are compared through the values they are converted into:
+///
+///```
+/// use core::num::{NonZeroI64, NonZeroU32, Wrapping};
+/// use subtle::{Choice, ConstantTimeEq, CtOption};
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct V { x: Option<u32> }
+/// let (some, none) = (V { x: Some(0) }, V { x: None });
+/// assert!(bool::from(some.ct_eq(&V { x: Some(0) })));
+/// assert!(bool::from(none.ct_eq(&V { x: None })));
+/// assert!(bool::from(!some.ct_eq(&none)));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Decoded(CtOption<u32>);
+/// let some = |x| Decoded(CtOption::new(x, Choice::from(1)));
+/// let none = |x| Decoded(CtOption::new(x, Choice::from(0)));
+/// assert!(bool::from(some(1).ct_eq(&some(1))));
+/// assert!(bool::from(none(1).ct_eq(&none(2))));
+/// assert!(bool::from(!some(1).ct_eq(&some(2))));
+/// assert!(bool::from(!some(1).ct_eq(&none(1))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct W { x: u8, flag: bool, maybe: Option<bool> }
+/// let w = W { x: 1, flag: true, maybe: Some(false) };
+/// assert!(bool::from(w.ct_eq(&W { x: 1, flag: true, maybe: Some(false) })));
+/// assert!(bool::from(!w.ct_eq(&W { x: 1, flag: false, maybe: Some(false) })));
+/// assert!(bool::from(!w.ct_eq(&W { x: 1, flag: true, maybe: Some(true) })));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Initial(char);
+/// assert!(bool::from(Initial('a').ct_eq(&Initial('a'))));
+/// assert!(bool::from(!Initial('a').ct_eq(&Initial('b'))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Handle { id: NonZeroU32, offset: Option<NonZeroI64> }
+/// let id = NonZeroU32::new(7).unwrap();
+/// let handle = Handle { id, offset: NonZeroI64::new(-3) };
+/// assert!(bool::from(handle.ct_eq(&Handle { id, offset: NonZeroI64::new(-3) })));
+/// assert!(bool::from(!handle.ct_eq(&Handle { id, offset: None })));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Counter(Wrapping<u64>, Wrapping<i128>);
+/// let counter = Counter(Wrapping(u64::MAX) + Wrapping(2), Wrapping(-1));
+/// assert!(bool::from(counter.ct_eq(&Counter(Wrapping(1), Wrapping(-1)))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Reading(#[ct(float_bits)] f64);
+/// assert!(bool::from(Reading(f64::NAN).ct_eq(&Reading(f64::NAN))));
+/// assert!(bool::from(!Reading(0.0).ct_eq(&Reading(-0.0))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct X(u128, i128);
+/// assert!(bool::from(X(1 << 100, -1).ct_eq(&X(1 << 100, -1))));
+/// assert!(bool::from(!X(1 << 100, -1).ct_eq(&X(1, -1))));
+///
+/// use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+/// #[derive(ConstantTimeEq)]
+/// struct Peer { ip: IpAddr, addr: SocketAddr }
+/// let local = Ipv4Addr::LOCALHOST;
+/// let peer = Peer { ip: local.into(), addr: (local, 80).into() };
+/// assert!(bool::from(peer.ct_eq(&Peer { ip: local.into(), addr: (local, 80).into() })));
+/// assert!(bool::from(!peer.ct_eq(&Peer { ip: local.into(), addr: (local, 81).into() })));
+/// assert!(bool::from(!peer.ct_eq(&Peer { ip: local.to_ipv6_mapped().into(), addr: (local, 80).into() })));
+///```
+///
+/// Fields of variable length are compared once they are marked `#[ct(variable_length)]`:
+///
+///```
+/// use std::borrow::Cow;
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Token { #[ct(variable_length)] bytes: Vec<u8> }
+/// let token = Token { bytes: vec![1, 2, 3] };
+/// assert!(bool::from(token.ct_eq(&Token { bytes: vec![1, 2, 3] })));
+/// assert!(bool::from(!token.ct_eq(&Token { bytes: vec![1, 2, 4] })));
+/// assert!(bool::from(!token.ct_eq(&Token { bytes: vec![1, 2] })));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Passphrase(#[ct(variable_length)] String);
+/// let phrase = Passphrase("correct horse".to_string());
+/// assert!(bool::from(phrase.ct_eq(&Passphrase("correct horse".to_string()))));
+/// assert!(bool::from(!phrase.ct_eq(&Passphrase("correct house".to_string()))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Label<'a>(#[ct(variable_length)] Cow<'a, str>, #[ct(variable_length)] Cow<'a, [u8]>);
+/// let label = Label(Cow::Borrowed("key"), Cow::Owned(vec![1, 2]));
+/// assert!(bool::from(label.ct_eq(&Label(Cow::Owned("key".to_string()), Cow::Borrowed(&[1, 2])))));
+/// assert!(bool::from(!label.ct_eq(&Label(Cow::Borrowed("kex"), Cow::Borrowed(&[1, 2])))));
+///
+/// #[cfg(feature = "heapless")]
+/// {
+///   #[derive(ConstantTimeEq)]
+///   struct Nonce(#[ct(variable_length)] heapless::Vec<u8, 12>);
+///   let nonce = Nonce(heapless::Vec::from_slice(&[1, 2, 3]).unwrap());
+///   assert!(bool::from(nonce.ct_eq(&Nonce(heapless::Vec::from_slice(&[1, 2, 3]).unwrap()))));
+///   assert!(bool::from(!nonce.ct_eq(&Nonce(heapless::Vec::from_slice(&[1, 2]).unwrap()))));
+/// }
+///
+/// #[cfg(feature = "smallvec")]
+/// {
+///   use smallvec::{smallvec, SmallVec};
+///   #[derive(ConstantTimeEq)]
+///   struct Path(#[ct(variable_length)] SmallVec<[u16; 4]>);
+///   let path: Path = Path(smallvec![1, 2, 3, 4, 5]);
+///   assert!(bool::from(path.ct_eq(&Path(smallvec![1, 2, 3, 4, 5]))));
+///   assert!(bool::from(!path.ct_eq(&Path(smallvec![1, 2, 3]))));
+/// }
+///
+/// #[cfg(feature = "bytes")]
+/// {
+///   #[derive(ConstantTimeEq)]
+///   struct Session { #[ct(variable_length)] token: bytes::Bytes }
+///   let session = Session { token: bytes::Bytes::from_static(b"abc") };
+///   assert!(bool::from(session.ct_eq(&Session { token: bytes::Bytes::from_static(b"abc") })));
+///   assert!(bool::from(!session.ct_eq(&Session { token: bytes::Bytes::from_static(b"abd") })));
+/// }
+///
+/// #[cfg(feature = "secrecy")]
+/// {
+///   use secrecy::{SecretBox, SecretString};
+///   #[derive(ConstantTimeEq)]
+///   struct Login { #[ct(variable_length)] password: SecretString, pin: SecretBox<u32> }
+///   let login = || Login { password: "hunter2".into(), pin: SecretBox::new(Box::new(1234)) };
+///   assert!(bool::from(login().ct_eq(&login())));
+///   assert!(bool::from(!login().ct_eq(&Login { pin: SecretBox::new(Box::new(0)), ..login() })));
+/// }
+///```
+///
+/// Tuples and arrays are compared element by element, or as whole slices or words:
+///
+///```
+/// use arrayvec::ArrayVec;
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Entry { slot: (u8, u64), tag: ([u8; 16], u32) }
+/// let entry = Entry { slot: (1, 2), tag: ([3; 16], 4) };
+/// assert!(bool::from(entry.ct_eq(&Entry { slot: (1, 2), tag: ([3; 16], 4) })));
+/// assert!(bool::from(!entry.ct_eq(&Entry { slot: (1, 2), tag: ([3; 16], 5) })));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Board { cells: [[bool; 3]; 3] }
+/// let board = Board { cells: [[false, true, false]; 3] };
+/// assert!(bool::from(board.ct_eq(&Board { cells: [[false, true, false]; 3] })));
+/// assert!(bool::from(!board.ct_eq(&Board { cells: [[true, true, false]; 3] })));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Pin(#[ct(array_vec)] ArrayVec<u8, 8>);
+/// let pin = Pin(ArrayVec::try_from(&[1, 2, 3][..]).unwrap());
+/// assert!(bool::from(pin.ct_eq(&Pin(ArrayVec::try_from(&[1, 2, 3][..]).unwrap()))));
+/// assert!(bool::from(!pin.ct_eq(&Pin(ArrayVec::try_from(&[1, 2, 0][..]).unwrap()))));
+/// assert!(bool::from(!pin.ct_eq(&Pin(ArrayVec::try_from(&[1, 2][..]).unwrap()))));
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Blob { len: u16, #[ct(words)] data: [u8; 1027] }
+/// let blob = Blob { len: 3, data: [7; 1027] };
+/// let mut data = [7; 1027];
+/// assert!(bool::from(blob.ct_eq(&Blob { len: 3, data })));
+/// data[1026] = 0;
+/// assert!(bool::from(!blob.ct_eq(&Blob { len: 3, data })));
+/// data[1026] = 7;
+/// data[8] = 0;
+/// assert!(bool::from(!blob.ct_eq(&Blob { len: 3, data })));
+///
+/// #[cfg(feature = "simd")]
+/// {
+///   #[derive(ConstantTimeEq)]
+///   struct Mac { algorithm: u8, tag: [u8; 70], flags: Option<u8> }
+///   let mac = Mac { algorithm: 1, tag: [3; 70], flags: None };
+///   let mut tag = [3; 70];
+///   assert!(bool::from(mac.ct_eq(&Mac { algorithm: 1, tag, flags: None })));
+///   tag[69] = 4;
+///   assert!(bool::from(!mac.ct_eq(&Mac { algorithm: 1, tag, flags: None })));
+/// }
+///
+/// #[cfg(feature = "generic-array")]
+/// {
+///   use generic_array::{typenum::U4, GenericArray};
+///   #[derive(ConstantTimeEq)]
+///   struct Tag(GenericArray<u8, U4>);
+///   let tag = Tag(GenericArray::from([1, 2, 3, 4]));
+///   assert!(bool::from(tag.ct_eq(&Tag(GenericArray::from([1, 2, 3, 4])))));
+///   assert!(bool::from(!tag.ct_eq(&Tag(GenericArray::from([1, 2, 3, 5])))));
+/// }
+///```
+///
+/// When every field is a primitive integer or an array of them, as in a MAC tag, `ct_eq()` instead
+/// ORs together the XOR of every pair of fields into a single accumulator, which is only compared
+/// with zero at the end. Byte arrays are still compared with a single slice comparison, whose
+/// result is folded into the accumulator, unless they are marked `#[ct(words)]`. Types with
+/// `#[ct(choice_array)]` or `#[ct(code_size)]` are compared as those options describe instead.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Scalar([u64; 4]);
+/// assert!(bool::from(Scalar([1, 2, 3, 4]).ct_eq(&Scalar([1, 2, 3, 4]))));
+/// assert!(bool::from(!Scalar([1, 2, 3, 4]).ct_eq(&Scalar([1, 2, 3, 5]))));
+///```
+///
+/// Generic types and lifetimes are supported. Each type parameter is required to implement
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html), and
+/// the comparison derives likewise bound them by the trait they implement.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq)]
+/// struct Frame<'a, T> { header: T, #[ct(variable_length)] body: &'a [u8] }
+/// let frame = Frame { header: 1u16, body: &[1, 2, 3] };
+/// assert!(bool::from(frame.ct_eq(&Frame { header: 1, body: &[1, 2, 3] })));
+/// assert!(bool::from(!frame.ct_eq(&Frame { header: 2, body: &[1, 2, 3] })));
+/// assert!(bool::from(!frame.ct_eq(&Frame { header: 1, body: &[1, 2, 4] })));
+///```
+///
+/// If a field's type doesn't implement the traits needed to compare it, the compiler error points
+/// at the type of that field, rather than at the derive.
+///
+/// Named fields may be conditionally compiled with `#[cfg(...)]`, which every derive copies onto
+/// the code it generates for that field.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// #[derive(ConstantTimeEq, subtle_derive::ConstHash, subtle_derive::RedactedDebug)]
+/// struct Session { id: u32, #[cfg(any())] trace: Vec<u8> }
+/// assert!(bool::from(Session { id: 1 }.ct_eq(&Session { id: 1 })));
+/// assert_eq!(format!("{:?}", Session { id: 1 }), "Session { id: [REDACTED] }");
+///```
+///
+/// Generated code calls every trait method by its full path, and gives its own locals hygienic
+/// names, so neither methods of the same name nor fields named e.g. `ret` can change what it does.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ConstantTimeEq;
+///
+/// /* Method resolution would pick this over the inherent `apply_eq()`, if it was called as one. */
+/// #[allow(dead_code)]
+/// trait Clash { fn apply_eq<T>(self, a: &T, b: &T); }
+/// impl<U> Clash for U { fn apply_eq<T>(self, _: &T, _: &T) {} }
+///
+/// #[derive(ConstantTimeEq)]
+/// #[ct(ct_ne)]
+/// struct Locals { ret: u8, eq: u8, acc: u8 }
+/// let locals = Locals { ret: 1, eq: 2, acc: 3 };
+/// assert!(bool::from(locals.ct_eq(&Locals { ret: 1, eq: 2, acc: 3 })));
+/// assert!(bool::from(locals.ct_ne(&Locals { ret: 1, eq: 2, acc: 4 })));
+///```
+#[proc_macro_derive(ConstantTimeEq, attributes(ct))]
+pub fn derive_eq(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let attrs = container_attrs(&attrs);
+  if attrs.derive_ct.eq || attrs.derive_ct.partial_ord {
+    Code::ConflictingOptions.panic(
+      "ConstantTimeEq is already generated by #[ct(eq(derive_ct))] or #[ct(partial_ord(derive_ct))]",
+    );
+  }
+
+  let ct_impl = ct_eq_impl(&ident, &generics, &attrs, &data);
+  /* Compare the archived form of the type too, if requested. */
+  let archived_impl = if attrs.archived {
+    ct_eq_impl(
+      &archived_ident(&ident),
+      &generics,
+      &attrs,
+      &archived_data(&data),
+    )
+  } else {
+    quote! {}
+  };
+
+  let output = quote! {
+    #ct_impl
+    #archived_impl
+  };
+
+  if attrs.kani {
+    /* Kani only runs harnesses it can name, so they stay outside the anonymous scope. */
+    let scoped = proc_macro2::TokenStream::from(with_subtle_path(output, attrs.krate.as_ref()));
+    let proofs = paths::rewrite_subtle_paths(
+      kani_proofs(&ident, &generics, &data),
+      &paths::subtle_path(attrs.krate.as_ref()),
+    );
+    quote!(#scoped #proofs).into()
+  } else {
+    with_subtle_path(output, attrs.krate.as_ref())
+  }
+}
+
+/* Generate a Kani proof harness checking that `ct_eq()` agrees with comparing each field with
+ * `==`, or comparing the bits of floats, for all values of the type. */
+fn kani_proofs(ident: &Ident, generics: &Generics, data: &Data) -> proc_macro2::TokenStream {
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("#[ct(kani)] can't be used on generic types");
+  }
+  let module = format_ident!(
+    "{}_ct_proof",
+    ident.unraw().to_string().to_lowercase(),
+    span = ident.span()
+  );
+  let expected = Ident::new("expected", Span::mixed_site());
+  let checks = field_eq_stmts(data, quote!(a), quote!(b), &expected);
+
+  let lints = allowed_lints();
+  quote! {
+    #[cfg(kani)]
+    #lints
+    mod #module {
+      #[::kani::proof]
+      fn ct_eq_matches_fields() {
+        let a: super::#ident = ::kani::any();
+        let b: super::#ident = ::kani::any();
+        #[allow(unused_mut)]
+        let mut #expected = true;
+        #(#checks)*
+        ::core::assert_eq!(
+          ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(&a, &b)),
+          #expected
+        );
+      }
+    }
+  }
+}
+
+/* Reject deriving `derive`, which implements Eq, for a type with a field marked
+ * `#[ct(max_depth = N)]`, since ct_eq() never holds for a list of more than N nodes, even compared
+ * with itself, so `==` wouldn't be reflexive. */
+fn reject_bounded_depth(data: &Data, derive: &str) {
+  if let Data::Struct(DataStruct { fields, .. }) = data {
+    if fields
+      .iter()
+      .any(|field| field_attrs(&field.attrs).max_depth.is_some())
+    {
+      Code::ConflictingOptions.panic(format_args!(
+        "{} can't be derived for a type with a #[ct(max_depth = N)] field, since Eq must be \
+         reflexive, but a list of more than N nodes never compares equal",
+        derive
+      ));
+    }
+  }
+}
+
+/* Generate PartialEq and Eq impls which delegate to ct_eq(). */
+fn const_eq_impls(ident: &Ident, generics: &Generics) -> proc_macro2::TokenStream {
+  let generics = bounded_generics(generics, quote!(::subtle::ConstantTimeEq));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::core::cmp::PartialEq for #ident #ty_generics #where_clause {
+      fn eq(&self, other: &Self) -> bool {
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(self, other))
+      }
+    }
+
+    #derived
+    impl #impl_generics ::core::cmp::Eq for #ident #ty_generics #where_clause {}
+  }
+}
+
+/// Implement [`PartialEq`] and [`Eq`] given a [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html) implementation.
+///
+/// With `#[ct(eq(derive_ct))]`, this also generates the [`subtle::ConstantTimeEq`] impl, as if
+/// [`ConstantTimeEq`](derive@ConstantTimeEq) had been derived (including any other `#[ct(...)]`
+/// options it accepts). The option only applies to this derive, so
+/// [`ConstantTimeEq`](derive@ConstantTimeEq) must not be derived as well.
+///
+/// [`subtle::ConstantTimeEq`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html
+///
+///```
+/// use subtle_derive::{ConstantTimeEq, ConstEq};
+///
+/// #[derive(Debug, ConstantTimeEq, ConstEq)]
+/// pub struct S(pub u8);
+///
+/// assert!(S(0) == S(0));
+/// assert!(S(0) != S(1));
+///
+/// #[derive(Debug, ConstEq)]
+/// #[ct(eq(derive_ct))]
+/// pub struct T { x: u8, y: u8 }
+///
+/// assert!(T { x: 0, y: 1 } == T { x: 0, y: 1 });
+/// assert!(T { x: 0, y: 1 } != T { x: 0, y: 2 });
+///```
+#[proc_macro_derive(ConstEq, attributes(ct))]
+pub fn derive_eq_impls(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let attrs = container_attrs(&attrs);
+  reject_bounded_depth(&data, "ConstEq");
+
+  if attrs.derive_ct.eq && attrs.derive_ct.partial_ord {
+    Code::ConflictingOptions.panic(
+      "#[ct(partial_ord(derive_ct))] already generates ConstantTimeEq, so #[ct(eq(derive_ct))] \
+       isn't needed",
+    );
+  }
+
+  /* Generate the ConstantTimeEq impl too, if requested. */
+  let ct_impl = if attrs.derive_ct.eq {
+    ct_eq_impl(&ident, &generics, &attrs, &data)
+  } else {
+    quote! {}
+  };
+
+  let eq_impls = const_eq_impls(&ident, &generics);
+
+  let output = quote! {
+    #ct_impl
+    #eq_impls
+  };
+
+  with_subtle_path(output, attrs.krate.as_ref())
+}
+
+/// Implement [`Hash`](core::hash::Hash) consistently with a [`ConstEq`](derive@ConstEq)
+/// implementation.
+///
+/// Every field is hashed unconditionally, in declaration order, so the sequence of calls into the
+/// [`Hasher`](core::hash::Hasher) depends only on the type and not on the field values (although
+/// any variable-length fields will still write their own lengths). Since the derived `ct_eq()` only
+/// holds when every pair of fields is equal, any two values it considers equal also hash
+/// identically, as [`Hash`](core::hash::Hash) requires.
+///
+/// A field marked `#[ct(flatten(a, b))]` only hashes the fields it names, as only those are
+/// compared, so values which differ in its other fields still hash identically.
+///
+///```
+/// use std::collections::HashSet;
+/// use subtle_derive::{ConstEq, ConstHash};
+///
+/// #[derive(Debug, ConstEq, ConstHash)]
+/// #[ct(eq(derive_ct))]
+/// pub struct S { x: u8, y: [u8; 4] }
+///
+/// let mut set = HashSet::new();
+/// assert!(set.insert(S { x: 0, y: [1, 2, 3, 4] }));
+/// assert!(!set.insert(S { x: 0, y: [1, 2, 3, 4] }));
+/// assert!(set.insert(S { x: 1, y: [1, 2, 3, 4] }));
+/// assert_eq!(set.len(), 2);
+///
+/// #[derive(Debug)]
+/// pub struct Version { pub major: u32, pub minor: u32, pub note: &'static str }
+///
+/// #[derive(Debug, ConstEq, ConstHash)]
+/// #[ct(eq(derive_ct))]
+/// pub struct Release { #[ct(flatten(major, minor))] version: Version, build: u64 }
+///
+/// let release = |note| Release { version: Version { major: 1, minor: 2, note }, build: 7 };
+/// assert_eq!(release("first"), release("second"));
+/// let mut set = HashSet::new();
+/// assert!(set.insert(release("first")));
+/// assert!(!set.insert(release("second")));
+///```
+#[proc_macro_derive(ConstHash, attributes(ct))]
+pub fn derive_hash(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let generics = bounded_generics(&generics, quote!(::core::hash::Hash));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  /* Hash each compared field in turn, so that fields with #[ct(flatten(...))] only hash the
+   * fields they list, as ct_eq() only compares those. */
+  let hash_stmts = compared_fields(&data).into_iter().map(|(path, field)| {
+    let cfgs = cfg_attrs(field);
+    quote! { #(#cfgs)* ::core::hash::Hash::hash(&self.#path, state); }
+  });
+
+  let derived = derived_attrs();
+  let output = quote! {
+    #derived
+    impl #impl_generics ::core::hash::Hash for #ident #ty_generics #where_clause {
+      fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+        #(#hash_stmts)*
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/* Generate a Debug impl which only shows the values of fields marked #[ct(debug_public)]. */
+fn redacted_debug_impl(
+  ident: &Ident,
+  generics: &Generics,
+  data: &Data,
+) -> proc_macro2::TokenStream {
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  /* Print names without any `r#` prefix, as the builtin Debug derive does. */
+  let name = ident.unraw().to_string();
+  /* Refer to public fields by value, and replace everything else with a placeholder. */
+  let field_values: Vec<proc_macro2::TokenStream> = struct_fields(data)
+    .into_iter()
+    .map(|(field_name, Field { attrs, .. })| {
+      if field_attrs(attrs).debug_public {
+        quote! { &self.#field_name }
+      } else {
+        quote! { &::core::format_args!("[REDACTED]") }
+      }
+    })
+    .collect();
+
+  /* Mirror the format of the builtin Debug derive for each kind of struct. */
+  let fmt_expr = match data {
+    Data::Struct(DataStruct {
+      fields: Fields::Named(_),
+      ..
+    }) => {
+      let field_names = struct_fields(data)
+        .into_iter()
+        .filter_map(|(_, field)| field.ident.as_ref().map(|ident| ident.unraw().to_string()));
+      /* Add each field in its own statement, so that it can carry the field's cfgs. */
+      let cfgs = field_cfgs(&struct_fields(data));
+      quote! {
+        let mut debug = f.debug_struct(#name);
+        #(#cfgs debug.field(#field_names, #field_values);)*
+        debug.finish()
+      }
+    },
+    Data::Struct(DataStruct {
+      fields: Fields::Unnamed(_),
+      ..
+    }) => quote! {
+      f.debug_tuple(#name)
+        #(.field(#field_values))*
+        .finish()
+    },
+    _ => quote! { f.write_str(#name) },
+  };
+
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::core::fmt::Debug for #ident #ty_generics #where_clause {
+      fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        #fmt_expr
+      }
+    }
+  }
+}
+
+/// Implement [`Debug`](core::fmt::Debug) without revealing the value of any field.
+///
+/// The type and field names are printed as usual, but each value is replaced by `[REDACTED]`.
+/// Fields which hold no secret data can be marked with `#[ct(debug_public)]` to print their values
+/// with their own [`Debug`](core::fmt::Debug) impls.
+///
+///```
+/// use subtle_derive::RedactedDebug;
+///
+/// #[derive(RedactedDebug)]
+/// struct Key { #[ct(debug_public)] id: u32, bytes: [u8; 4] }
+/// let key = Key { id: 3, bytes: [1, 2, 3, 4] };
+/// assert_eq!(format!("{:?}", key), "Key { id: 3, bytes: [REDACTED] }");
+///
+/// #[derive(RedactedDebug)]
+/// struct T(u8, #[ct(debug_public)] bool);
+/// assert_eq!(format!("{:?}", T(1, true)), "T([REDACTED], true)");
+///
+/// #[derive(RedactedDebug)]
+/// struct U;
+/// assert_eq!(format!("{:?}", U), "U");
+///
+/// #[derive(RedactedDebug)]
+/// struct Header { #[ct(debug_public)] r#type: u8, r#ref: u32 }
+/// let header = Header { r#type: 1, r#ref: 2 };
+/// assert_eq!(format!("{:?}", header), "Header { type: 1, ref: [REDACTED] }");
+///```
+#[proc_macro_derive(RedactedDebug, attributes(ct))]
+pub fn derive_redacted_debug(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    data,
+    ..
+  } = parse_macro_input!(input);
+
+  with_subtle_path(redacted_debug_impl(&ident, &generics, &data), None)
+}
+
+/* Generate Zeroize and ZeroizeOnDrop impls which zeroize every field. */
+fn zeroize_impls(ident: &Ident, generics: &Generics, data: &Data) -> proc_macro2::TokenStream {
+  /* Drop impls can't add bounds the type itself doesn't declare, so generic secrets must bound
+   * their type parameters by Zeroize themselves. */
+  let (drop_generics, _, _) = generics.split_for_impl();
+  let bounded = bounded_generics(generics, quote!(::zeroize::Zeroize));
+  let (impl_generics, ty_generics, where_clause) = bounded.split_for_impl();
+  let zeroize_stmts = struct_fields(data).into_iter().map(|(name, field)| {
+    let cfgs = cfg_attrs(Some(field));
+    quote! { #(#cfgs)* ::zeroize::Zeroize::zeroize(&mut self.#name); }
+  });
+
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::zeroize::Zeroize for #ident #ty_generics #where_clause {
+      fn zeroize(&mut self) {
+        #(#zeroize_stmts)*
+      }
+    }
+
+    #derived
+    impl #drop_generics ::core::ops::Drop for #ident #ty_generics #where_clause {
+      fn drop(&mut self) {
+        ::zeroize::Zeroize::zeroize(self);
+      }
+    }
+
+    #derived
+    impl #impl_generics ::zeroize::ZeroizeOnDrop for #ident #ty_generics #where_clause {}
+  }
+}
+
+/// Derive everything needed for a type holding secret data at once.
+///
+/// This generates the same impls as deriving [`ConstantTimeEq`](derive@ConstantTimeEq),
+/// [`ConstEq`](derive@ConstEq), and [`RedactedDebug`](derive@RedactedDebug), and accepts the same
+/// `#[ct(...)]` options. With `#[ct(zeroize)]`, it also implements
+/// [`zeroize::Zeroize`](https://docs.rs/zeroize/latest/zeroize/trait.Zeroize.html) by zeroizing
+/// every field, along with [`Drop`] and
+/// [`zeroize::ZeroizeOnDrop`](https://docs.rs/zeroize/latest/zeroize/trait.ZeroizeOnDrop.html) to
+/// zeroize the value when it goes out of scope. The crate must then depend on `zeroize`, and since
+/// the type implements [`Drop`], its fields can no longer be moved out of it.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::Secret;
+///
+/// #[derive(Secret)]
+/// struct Key { #[ct(debug_public)] id: u32, bytes: [u8; 4] }
+/// let k1 = Key { id: 0, bytes: [1, 2, 3, 4] };
+/// let k2 = Key { id: 0, bytes: [1, 2, 3, 5] };
+/// assert!(bool::from(k1.ct_eq(&k1)));
+/// assert!(k1 != k2);
+/// assert_eq!(format!("{:?}", k1), "Key { id: 0, bytes: [REDACTED] }");
+///
+/// #[derive(Secret)]
+/// #[ct(zeroize)]
+/// struct Seed { #[ct(debug_public)] id: u32, bytes: [u8; 4] }
+///
+/// use zeroize::Zeroize;
+/// let mut seed = Seed { id: 1, bytes: [1, 2, 3, 4] };
+/// seed.zeroize();
+/// assert!(seed == Seed { id: 0, bytes: [0; 4] });
+///```
+#[proc_macro_derive(Secret, attributes(ct))]
+pub fn derive_secret(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+
+  let attrs = container_attrs(&attrs);
+  reject_bounded_depth(&data, "Secret");
+
+  let ct_impl = ct_eq_impl(&ident, &generics, &attrs, &data);
+  let eq_impls = const_eq_impls(&ident, &generics);
+  let debug_impl = redacted_debug_impl(&ident, &generics, &data);
+  let zeroize_impls = if attrs.zeroize {
+    zeroize_impls(&ident, &generics, &data)
+  } else {
+    quote! {}
+  };
+
+  let output = quote! {
+    #ct_impl
+    #eq_impls
+    #debug_impl
+    #zeroize_impls
+  };
+
+  with_subtle_path(output, attrs.krate.as_ref())
+}
+
+/// Generate a constructor which validates every field in constant time.
+///
+/// The generated `new_checked()` takes each field as an argument in declaration order, and returns
+/// a [`subtle::CtOption`](https://docs.rs/subtle/latest/subtle/struct.CtOption.html) which is only
+/// `Some` if every field is valid. All checks are evaluated and combined with `&` regardless of
+/// each other's results. Fields are validated with:
+/// - `#[ct(check = "path::to::function")]`: a function taking a reference to the field and
+///   returning a [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html). This
+///   may be given multiple times.
+/// - `#[ct(range = "1..=N")]`: the field must lie within this range, compared with
+///   `ct_lt()`/`ct_gt()`. Either bound may be omitted.
+///
+/// Arguments for the fields of tuple structs are named `field_0`, `field_1`, etc.
+///
+///```
+/// use subtle::Choice;
+/// use subtle_derive::CtNew;
+///
+/// fn is_odd(x: &u8) -> Choice { Choice::from(x & 1) }
+///
+/// #[derive(CtNew)]
+/// struct S {
+///   #[ct(check = "is_odd")]
+///   x: u8,
+///   #[ct(range = "1..=10")]
+///   y: u32,
+///   z: u8,
+/// }
+/// assert!(bool::from(S::new_checked(3, 10, 0).is_some()));
+/// assert!(bool::from(S::new_checked(2, 10, 0).is_none()));
+/// assert!(bool::from(S::new_checked(3, 0, 0).is_none()));
+/// assert!(bool::from(S::new_checked(3, 11, 0).is_none()));
+///
+/// #[derive(CtNew)]
+/// struct T(#[ct(range = "..8")] u8);
+/// assert!(bool::from(T::new_checked(7).is_some()));
+/// assert!(bool::from(T::new_checked(8).is_none()));
+///
+/// #[derive(CtNew)]
+/// struct Limits { #[ct(range = "..8")] r#in: u8, r#out: u8 }
+/// assert!(bool::from(Limits::new_checked(7, 9).is_some()));
+/// assert!(bool::from(Limits::new_checked(8, 9).is_none()));
+///
+/// #[derive(CtNew)]
+/// struct Tagged<T> { #[ct(range = "..4")] tag: u8, value: T }
+/// assert!(bool::from(Tagged::new_checked(3, "x").is_some()));
+/// assert!(bool::from(Tagged::new_checked(4, "x").is_none()));
+///```
+#[proc_macro_derive(CtNew, attributes(ct))]
+pub fn derive_new(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let ContainerAttrs { krate, .. } = container_attrs(&attrs);
+
+  let fields = struct_fields(&data);
+  let locals = field_locals(&data);
+  let types: Vec<&Type> = fields.iter().map(|(_, field)| &field.ty).collect();
+  let members: Vec<&Member> = fields.iter().map(|(name, _)| name).collect();
+  let checks = validity_stmts(locals.iter().zip(fields.iter().map(|(_, field)| *field)));
+  let cfgs = field_cfgs(&fields);
+  let is_valid = Ident::new("is_valid", Span::mixed_site());
+
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Construct a new instance, which is only `Some` if every field is valid.
+      #[must_use]
+      pub fn new_checked(#(#cfgs #locals: #types),*) -> ::subtle::CtOption<Self> {
+        #[allow(unused_mut)]
+        let mut #is_valid = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8);
+        #(#checks)*
+        ::subtle::CtOption::new(Self { #(#cfgs #members: #locals),* }, #is_valid)
+      }
+    }
+  };
+
+  with_subtle_path(output, krate.as_ref())
+}
+
+/// Generate a constructor which decodes and validates a fixed-width byte encoding in constant time.
+///
+/// The generated `from_bytes()` accepts a byte array containing each field's encoding one after
+/// another in declaration order, and returns a
+/// [`subtle::CtOption`](https://docs.rs/subtle/latest/subtle/struct.CtOption.html) which is only
+/// `Some` if every field is valid. Every field is decoded and validated regardless of the others,
+/// using the same `#[ct(check = "...")]` and `#[ct(range = "...")]` options as
+/// [`CtNew`](derive@CtNew). Fields are decoded according to their type:
+/// - Primitive integers are decoded with `from_le_bytes()`, or `from_be_bytes()` if the type is
+///   marked `#[ct(big_endian)]`.
+/// - `[u8; N]` fields are copied directly.
+/// - Any other field must be marked `#[ct(bytes = N)]` with the width of its encoding, and its type
+///   must provide `fn from_bytes(&[u8; N]) -> CtOption<Self>` (e.g. by deriving
+///   [`CtFromBytes`](derive@CtFromBytes)) as well as implement [`Default`] and
+///   [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html).
+///   If it fails to decode, the default value is used in its place and the result is `None`.
+///
+/// With `#[ct(hex)]` on the type, a `from_hex()` constructor is generated too, which decodes a
+/// string of twice as many hex digits (in either case) into the bytes without branching on them,
+/// and then decodes and validates the bytes as `from_bytes()` does. Only the length of the string
+/// is checked separately, since it isn't secret.
+///
+/// Generic types aren't supported, since the width of the encoding must be known up front.
+///
+/// With `#[ct(zeroize)]` on the type, the temporary copy of each field's bytes is zeroized once it
+/// has been decoded, so the crate must then depend on `zeroize`.
+///
+///```
+/// use subtle_derive::CtFromBytes;
+///
+/// #[derive(CtFromBytes)]
+/// struct Header { #[ct(range = "1..=2")] version: u16, tag: [u8; 2] }
+///
+/// let header = Header::from_bytes(&[1, 0, 0xAB, 0xCD]).unwrap();
+/// assert_eq!(header.version, 1);
+/// assert_eq!(header.tag, [0xAB, 0xCD]);
+/// assert!(bool::from(Header::from_bytes(&[3, 0, 0xAB, 0xCD]).is_none()));
+///
+/// #[derive(CtFromBytes)]
+/// #[ct(big_endian)]
+/// struct T(u16, u8);
+/// let t = T::from_bytes(&[1, 2, 3]).unwrap();
+/// assert_eq!((t.0, t.1), (0x0102, 3));
+///
+/// #[derive(CtFromBytes)]
+/// #[ct(hex)]
+/// struct ApiKey([u8; 4]);
+/// assert_eq!(ApiKey::from_hex("00aBcDef").unwrap().0, [0x00, 0xAB, 0xCD, 0xEF]);
+/// assert!(bool::from(ApiKey::from_hex("00aBcDeg").is_none()));
+/// assert!(bool::from(ApiKey::from_hex("00aBcD").is_none()));
+///```
+#[proc_macro_derive(CtFromBytes, attributes(ct))]
+pub fn derive_from_bytes(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  /* The width of the encoding must be known without knowing the type parameters. */
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("CtFromBytes can't be derived for generic types");
+  }
+  let ContainerAttrs {
+    big_endian,
+    krate,
+    hex,
+    zeroize,
+    ..
+  } = container_attrs(&attrs);
+
+  let (slots, total_width) = byte_layout(&data);
+  let from_bytes = if big_endian {
+    quote! { from_be_bytes }
+  } else {
+    quote! { from_le_bytes }
+  };
+
+  /* Copy out each field's bytes, then decode them. The locals are hygienic, since they're in scope
+   * alongside locals named after each field. */
+  let is_valid = Ident::new("is_valid", Span::mixed_site());
+  let bytes = Ident::new("bytes", Span::mixed_site());
+  let buf = Ident::new("buf", Span::mixed_site());
+  let decode_stmts: Vec<proc_macro2::TokenStream> = slots
+    .iter()
+    .map(
+      |ByteSlot {
+         local,
+         field,
+         kind,
+         width,
+         offset,
+         ..
+       }| {
+        let ty = &field.ty;
+        let decoded = match kind {
+          ByteField::Int => quote! { <#ty>::#from_bytes(#buf) },
+          ByteField::Bytes => quote! { #buf },
+          ByteField::Nested => quote! {{
+            let decoded = <#ty>::from_bytes(&#buf);
+            #is_valid &= decoded.is_some();
+            decoded.unwrap_or(::core::default::Default::default())
+          }},
+        };
+        let zeroize_buf = zeroize_temporary(zeroize, quote!(#buf));
+        quote! {
+          let #local: #ty = {
+            let mut #buf = [0u8; #width];
+            #buf.copy_from_slice(&#bytes[#offset..#offset + #width]);
+            let decoded = #decoded;
+            #zeroize_buf
+            decoded
+          };
+        }
+      },
+    )
+    .collect();
+  let checks = validity_stmts(slots.iter().map(|slot| (&slot.local, slot.field)));
+  let members: Vec<&Member> = slots.iter().map(|slot| &slot.member).collect();
+  let locals: Vec<&Ident> = slots.iter().map(|slot| &slot.local).collect();
+
+  /* Decode hex digits into the bytes without branching on them, then decode the bytes as usual. */
+  let from_hex = if hex {
+    let zeroize_bytes = zeroize_temporary(zeroize, quote!(#bytes));
+    quote! {
+      /// Decode an instance from a hex string of the encoded bytes, which is only `Some` if the
+      /// string has the right length, every digit is valid, and every field is valid.
+      #[must_use]
+      pub fn from_hex(hex: &str) -> ::subtle::CtOption<Self> {
+        let hex = hex.as_bytes();
+        /* The length is public, so it can be checked directly. */
+        let mut #is_valid = <::subtle::Choice as ::core::convert::From<u8>>::from(
+          <u8 as ::core::convert::From<bool>>::from(hex.len() == 2 * #total_width),
+        );
+        let mut #bytes = [0u8; #total_width];
+        for (i, byte) in ::core::iter::Iterator::enumerate(#bytes.iter_mut()) {
+          for digit in hex.get(2 * i..2 * i + 2).unwrap_or(&[0, 0]) {
+            let decimal = digit.wrapping_sub(b'0');
+            let is_decimal = ::subtle::ConstantTimeLess::ct_lt(&decimal, &10);
+            let alpha = (digit | 0x20).wrapping_sub(b'a');
+            let is_alpha = ::subtle::ConstantTimeLess::ct_lt(&alpha, &6);
+            #is_valid &= is_decimal | is_alpha;
+            let nibble = <u8 as ::subtle::ConditionallySelectable>::conditional_select(
+              &0, &decimal, is_decimal,
+            ) | <u8 as ::subtle::ConditionallySelectable>::conditional_select(
+              &0, &alpha.wrapping_add(10), is_alpha,
+            );
+            *byte = (*byte << 4) | nibble;
+          }
+        }
+        #(#decode_stmts)*
+        #(#checks)*
+        #zeroize_bytes
+        ::subtle::CtOption::new(Self { #(#members: #locals),* }, #is_valid)
+      }
+    }
+  } else {
+    quote! {}
+  };
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #ident {
+      /// Decode an instance from bytes, which is only `Some` if every field is valid.
+      #[must_use]
+      pub fn from_bytes(#bytes: &[u8; #total_width]) -> ::subtle::CtOption<Self> {
+        #[allow(unused_mut)]
+        let mut #is_valid = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8);
+        #(#decode_stmts)*
+        #(#checks)*
+        ::subtle::CtOption::new(Self { #(#members: #locals),* }, #is_valid)
+      }
+
+      #from_hex
+    }
+  };
+
+  with_subtle_path(output, krate.as_ref())
+}
+
+/// Generate a method which encodes every field into a fixed-width byte array.
+///
+/// The generated `to_bytes()` writes each field's encoding one after another in declaration order,
+/// without any data-dependent control flow. The layout is the same as for
+/// [`CtFromBytes`](derive@CtFromBytes), so the two can be derived together to round-trip values:
+/// - Primitive integers are encoded with `to_le_bytes()`, or `to_be_bytes()` if the type is marked
+///   `#[ct(big_endian)]`.
+/// - `[u8; N]` fields are copied directly.
+/// - Any other field must be marked `#[ct(bytes = N)]` with the width of its encoding, and its type
+///   must provide `fn to_bytes(&self) -> [u8; N]` (e.g. by deriving
+///   [`CtToBytes`](derive@CtToBytes)).
+///
+/// As with [`CtFromBytes`](derive@CtFromBytes), generic types aren't supported.
+///
+/// With `#[ct(zeroize)]` on the type, the temporary encoding of each field is zeroized once it has
+/// been copied into place, so the crate must then depend on `zeroize`. The returned bytes are the
+/// caller's to zeroize.
+///
+///```
+/// use subtle_derive::{CtFromBytes, CtToBytes};
+///
+/// #[derive(Default, Clone, Copy, CtFromBytes, CtToBytes)]
+/// struct Inner { x: u16 }
+/// impl subtle::ConditionallySelectable for Inner {
+///   fn conditional_select(a: &Self, b: &Self, choice: subtle::Choice) -> Self {
+///     Inner { x: u16::conditional_select(&a.x, &b.x, choice) }
+///   }
+/// }
+///
+/// #[derive(CtFromBytes, CtToBytes)]
+/// struct Outer { tag: [u8; 2], #[ct(bytes = 2)] inner: Inner, y: u32 }
+///
+/// let outer = Outer { tag: [0xAB, 0xCD], inner: Inner { x: 0x0102 }, y: 3 };
+/// let bytes = outer.to_bytes();
+/// assert_eq!(bytes, [0xAB, 0xCD, 0x02, 0x01, 3, 0, 0, 0]);
+/// let decoded = Outer::from_bytes(&bytes).unwrap();
+/// assert_eq!((decoded.tag, decoded.inner.x, decoded.y), ([0xAB, 0xCD], 0x0102, 3));
+///```
+#[proc_macro_derive(CtToBytes, attributes(ct))]
+pub fn derive_to_bytes(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  /* The width of the encoding must be known without knowing the type parameters. */
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("CtToBytes can't be derived for generic types");
+  }
+  let ContainerAttrs {
+    big_endian,
+    krate,
+    zeroize,
+    ..
+  } = container_attrs(&attrs);
+
+  let (slots, total_width) = byte_layout(&data);
+  let to_bytes = if big_endian {
+    quote! { to_be_bytes }
+  } else {
+    quote! { to_le_bytes }
+  };
+
+  /* Encode each field, then copy its bytes into place. */
+  let encode_stmts = slots.iter().map(
+    |ByteSlot {
+       member,
+       kind,
+       width,
+       offset,
+       ..
+     }| {
+      let encoded = match kind {
+        ByteField::Int => quote! { self.#member.#to_bytes() },
+        ByteField::Bytes => quote! { self.#member },
+        ByteField::Nested => quote! { self.#member.to_bytes() },
+      };
+      let zeroize_encoded = zeroize_temporary(zeroize, quote!(encoded));
+      quote! {
+        #[allow(unused_mut)]
+        let mut encoded = #encoded;
+        bytes[#offset..#offset + #width].copy_from_slice(&encoded);
+        #zeroize_encoded
+      }
+    },
+  );
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #ident {
+      /// Encode every field into bytes.
+      #[must_use]
+      pub fn to_bytes(&self) -> [u8; #total_width] {
+        #[allow(unused_mut)]
+        let mut bytes = [0u8; #total_width];
+        #(#encode_stmts)*
+        bytes
+      }
+    }
+  };
+
+  with_subtle_path(output, krate.as_ref())
+}
+
+/// Implement [`serde::Deserialize`](https://docs.rs/serde/latest/serde/trait.Deserialize.html) by
+/// deserializing every field, then validating them in constant time.
+///
+/// Fields are validated with the same `#[ct(check = "...")]` and `#[ct(range = "...")]` options as
+/// [`CtNew`](derive@CtNew), and every check is evaluated regardless of the others' results. Only
+/// once all of them have been combined does deserialization fail with a single error, which
+/// doesn't say which field was invalid. Any `#[serde(...)]` attributes on the type or its fields
+/// are applied to the deserialization of the raw fields, so the crate using this derive must
+/// depend on `serde` with its `derive` feature. Generic types aren't supported.
+///
+/// This derive is only available with the `serde` feature.
+///
+///```
+/// use subtle_derive::CtDeserialize;
+///
+/// #[derive(CtDeserialize)]
+/// struct Config {
+///   #[ct(range = "1..=10")]
+///   rounds: u32,
+///   #[serde(rename = "key")]
+///   secret: [u8; 2],
+/// }
+/// let config: Config = serde_json::from_str(r#"{ "rounds": 3, "key": [1, 2] }"#).unwrap();
+/// assert_eq!((config.rounds, config.secret), (3, [1, 2]));
+/// assert!(serde_json::from_str::<Config>(r#"{ "rounds": 11, "key": [1, 2] }"#).is_err());
+///```
+#[cfg(feature = "serde")]
+#[proc_macro_derive(CtDeserialize, attributes(ct, serde))]
+pub fn derive_deserialize(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  /* The unvalidated copy of the type is deserialized on its own, without the type parameters. */
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("CtDeserialize can't be derived for generic types");
+  }
+  let ContainerAttrs { krate, .. } = container_attrs(&attrs);
+
+  let fields = struct_fields(&data);
+  let locals = field_locals(&data);
+  let types: Vec<&Type> = fields.iter().map(|(_, field)| &field.ty).collect();
+  let members: Vec<&Member> = fields.iter().map(|(name, _)| name).collect();
+  let checks = validity_stmts(locals.iter().zip(fields.iter().map(|(_, field)| *field)));
+  let cfgs = field_cfgs(&fields);
+  let is_valid = Ident::new("is_valid", Span::mixed_site());
+
+  /* Deserialize a copy of the type with the same shape and serde attributes, but no validation. */
+  let serde_attrs = |attrs: &[Attribute]| -> Vec<Attribute> {
+    attrs
+      .iter()
+      .filter(|attr| attr.path.is_ident("serde"))
+      .cloned()
+      .collect()
+  };
+  let container_serde_attrs = serde_attrs(&attrs);
+  let field_serde_attrs: Vec<Vec<Attribute>> = fields
+    .iter()
+    .map(|(_, field)| serde_attrs(&field.attrs))
+    .collect();
+  let (raw_type, raw_pattern) = match &data {
+    Data::Struct(DataStruct {
+      fields: Fields::Named(_),
+      ..
+    }) => (
+      quote! { struct Raw { #(#cfgs #(#field_serde_attrs)* #locals: #types),* } },
+      quote! { Raw { #(#cfgs #locals),* } },
+    ),
+    _ => (
+      quote! { struct Raw(#(#(#field_serde_attrs)* #types),*); },
+      quote! { Raw(#(#locals),*) },
+    ),
+  };
+
+  let derived = derived_attrs();
+  /* The raw type is only visible within the anonymous scope of the output. */
+  let output = quote! {
+    #[derive(::serde::Deserialize)]
+    #(#container_serde_attrs)*
+    #raw_type
+
+    #derived
+    impl<'de> ::serde::Deserialize<'de> for #ident {
+      fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+      where
+        D: ::serde::Deserializer<'de>,
+      {
+        let #raw_pattern = <Raw as ::serde::Deserialize<'de>>::deserialize(deserializer)?;
+        #[allow(unused_mut)]
+        let mut #is_valid = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8);
+        #(#checks)*
+        ::core::option::Option::from(::subtle::CtOption::new(
+          Self { #(#cfgs #members: #locals),* },
+          #is_valid,
+        ))
+        .ok_or_else(|| <D::Error as ::serde::de::Error>::custom("invalid value"))
+      }
+    }
+  };
+
+  with_subtle_path(output, krate.as_ref())
+}
+
+/* Generate a ConstantTimeGreater impl which compares every field lexicographically. */
+fn ct_gt_impl(
+  ident: &Ident,
+  generics: &Generics,
+  attrs: &ContainerAttrs,
+  data: &Data,
+) -> proc_macro2::TokenStream {
+  let generics = bounded_generics(generics, quote!(::subtle::ConstantTimeGreater));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  /* Generate the function body of a ct_gt() implementation. */
+  let gt_block = with_barrier(
+    fields_block(&compared_fields(data), "IteratedGreater", "apply_gt", attrs),
+    attrs,
+  );
+
+  let inline = inline_attr(attrs);
+
+  /* Insert the ct_gt() block into the quoted trait method. */
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::subtle::ConstantTimeGreater for #ident #ty_generics #where_clause {
+      #inline
+      fn ct_gt(&self, other: &Self) -> ::subtle::Choice {
+        #gt_block
+      }
+    }
+  }
+}
+
+/// Derive macro for
+/// [`subtle::ConstantTimeGreater`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html)
+/// implemented using
+/// [`subtle::IteratedGreater`](https://docs.rs/subtle/latest/subtle/struct.IteratedGreater.html).
+///
+///```
+/// use subtle::ConstantTimeGreater;
+/// use subtle_derive::ConstantTimeGreater;
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct S { x: u8, y: u8 }
+/// let s1 = S { x: 0, y: 1 };
+/// let s2 = S { x: 0, y: 2 };
+/// assert!(bool::from(!s1.ct_gt(&s1)));
+/// assert!(bool::from(s2.ct_gt(&s1)));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct T(u8, u8);
+/// let t1 = T(0, 1);
+/// let t2 = T(0, 2);
+/// assert!(bool::from(!t1.ct_gt(&t1)));
+/// assert!(bool::from(t2.ct_gt(&t1)));
+///
+/// #[derive(subtle_derive::ConstantTimeEq, ConstantTimeGreater)]
+/// struct Limb(u32);
+/// #[derive(ConstantTimeGreater)]
+/// struct Wide { limbs: [Limb; 2], grid: [[Option<u8>; 2]; 2] }
+/// let wide = |hi, lo, cell| Wide { limbs: [Limb(hi), Limb(lo)], grid: [[None, cell]; 2] };
+/// assert!(bool::from(wide(1, 0, None).ct_gt(&wide(0, 9, None))));
+/// assert!(bool::from(!wide(1, 0, None).ct_gt(&wide(1, 1, None))));
+/// assert!(bool::from(wide(1, 0, Some(0)).ct_gt(&wide(1, 0, None))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct Version((u8, u16));
+/// assert!(bool::from(Version((1, 0)).ct_gt(&Version((0, 9)))));
+/// assert!(bool::from(!Version((1, 0)).ct_gt(&Version((1, 1)))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct U(Option<u8>);
+/// assert!(bool::from(U(Some(0)).ct_gt(&U(None))));
+/// assert!(bool::from(U(Some(2)).ct_gt(&U(Some(1)))));
+/// assert!(bool::from(!U(None).ct_gt(&U(None))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct V(bool, u8);
+/// assert!(bool::from(V(true, 0).ct_gt(&V(false, 1))));
+/// assert!(bool::from(!V(false, 1).ct_gt(&V(true, 0))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct W(u128, i128);
+/// assert!(bool::from(W(1 << 100, 0).ct_gt(&W(1, 0))));
+/// assert!(bool::from(W(0, 1).ct_gt(&W(0, -1))));
+/// assert!(bool::from(W(0, -1).ct_gt(&W(0, i128::MIN))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct X { a: i8, b: i32, c: isize }
+/// assert!(bool::from(X { a: 1, b: 0, c: 0 }.ct_gt(&X { a: -1, b: 5, c: 5 })));
+/// assert!(bool::from(X { a: -1, b: -2, c: 0 }.ct_gt(&X { a: -1, b: i32::MIN, c: 5 })));
+/// assert!(bool::from(!X { a: -1, b: 0, c: -1 }.ct_gt(&X { a: -1, b: 0, c: 0 })));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct Y(char);
+/// assert!(bool::from(Y('b').ct_gt(&Y('a'))));
+/// assert!(bool::from(!Y('a').ct_gt(&Y('é'))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct Z(#[ct(float_total_order)] f32);
+/// assert!(bool::from(Z(1.5).ct_gt(&Z(-2.0))));
+/// assert!(bool::from(Z(-1.0).ct_gt(&Z(-2.0))));
+/// assert!(bool::from(Z(0.0).ct_gt(&Z(-0.0))));
+/// assert!(bool::from(Z(f32::NAN).ct_gt(&Z(f32::INFINITY))));
+///
+/// use core::time::Duration;
+/// #[derive(ConstantTimeGreater)]
+/// struct Expiry(Duration);
+/// assert!(bool::from(Expiry(Duration::new(5, 1)).ct_gt(&Expiry(Duration::new(4, 999)))));
+/// assert!(bool::from(Expiry(Duration::new(5, 1)).ct_gt(&Expiry(Duration::new(5, 0)))));
+/// assert!(bool::from(!Expiry(Duration::new(5, 0)).ct_gt(&Expiry(Duration::new(5, 0)))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct Digest([u8; 4]);
+/// assert!(bool::from(Digest([1, 0, 0, 0]).ct_gt(&Digest([0, 9, 9, 9]))));
+/// assert!(bool::from(!Digest([1, 2, 3, 4]).ct_gt(&Digest([1, 2, 3, 4]))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// struct Buffer(#[ct(words)] [u8; 10]);
+/// let mut buffer = [0; 10];
+/// buffer[7] = 1;
+/// assert!(bool::from(Buffer(buffer).ct_gt(&Buffer([0, 0, 0, 0, 0, 0, 0, 0, 9, 9]))));
+/// assert!(bool::from(Buffer([0, 0, 0, 0, 0, 0, 0, 0, 0, 2]).ct_gt(&Buffer([0; 10]))));
+/// assert!(bool::from(!Buffer([0; 10]).ct_gt(&Buffer(buffer))));
+///
+/// #[derive(ConstantTimeGreater)]
+/// #[ct(choice_array)]
+/// struct Version(u8, u8, u8);
+/// assert!(bool::from(Version(1, 2, 0).ct_gt(&Version(1, 1, 9))));
+/// assert!(bool::from(Version(2, 0, 0).ct_gt(&Version(1, 9, 9))));
+/// assert!(bool::from(!Version(1, 2, 3).ct_gt(&Version(1, 2, 3))));
+/// assert!(bool::from(!Version(1, 2, 3).ct_gt(&Version(1, 3, 0))));
+///```
+#[proc_macro_derive(ConstantTimeGreater, attributes(ct))]
+pub fn derive_gt(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let attrs = container_attrs(&attrs);
+  if attrs.derive_ct.partial_ord {
+    Code::ConflictingOptions.panic(
+      "ConstantTimeGreater is already generated by ConstPartialOrd with \
+       #[ct(partial_ord(derive_ct))]",
+    );
+  }
+
+  let ct_impl = ct_gt_impl(&ident, &generics, &attrs, &data);
+  /* Compare the archived form of the type too, if requested. */
+  let archived_impl = if attrs.archived {
+    ct_gt_impl(
+      &archived_ident(&ident),
+      &generics,
+      &attrs,
+      &archived_data(&data),
+    )
+  } else {
+    quote! {}
+  };
+
+  let output = quote! {
+    #ct_impl
+    #archived_impl
+  };
+
+  with_subtle_path(output, attrs.krate.as_ref())
+}
+
+/* Generate a ConstantTimeLess impl which compares every field lexicographically. */
+fn ct_lt_impl(
+  ident: &Ident,
+  generics: &Generics,
+  attrs: &ContainerAttrs,
+  data: &Data,
+) -> proc_macro2::TokenStream {
+  let generics = bounded_generics(generics, quote!(::subtle::ConstantTimeLess));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  /* Generate the function body of a ct_lt() implementation, which shares the ct_gt()
+   * implementation with the arguments swapped if only one copy of the comparison should be
+   * generated. */
+  let lt_block = if attrs.code_size {
+    quote! { ::subtle::ConstantTimeGreater::ct_gt(other, self) }
+  } else {
+    fields_block(&compared_fields(data), "IteratedLess", "apply_lt", attrs)
+  };
+  let lt_block = with_barrier(lt_block, attrs);
+
+  let inline = inline_attr(attrs);
+
+  /* Insert the ct_lt() block into the quoted trait method. */
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::subtle::ConstantTimeLess for #ident #ty_generics #where_clause {
+      #inline
+      fn ct_lt(&self, other: &Self) -> ::subtle::Choice {
+        #lt_block
+      }
+    }
+  }
+}
+
+/// Derive macro for [`subtle::ConstantTimeLess`] implemented using
+/// [`subtle::IteratedLess`](https://docs.rs/subtle/latest/subtle/struct.IteratedLess.html).
+///
+/// The generated `ct_lt()` is computed directly from the fields, and never calls `ct_gt()` on the
+/// type itself, except with `#[ct(code_size)]` (see [`ConstantTimeEq`](derive@ConstantTimeEq)),
+/// where it calls `ct_gt()` with the arguments swapped. However, [`subtle::ConstantTimeLess`] has
+/// [`subtle::ConstantTimeGreater`] as a supertrait, so the type must still implement
+/// [`subtle::ConstantTimeGreater`], either by deriving [`ConstantTimeGreater`] or by hand.
+///
+/// [`subtle::ConstantTimeGreater`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html
+/// [`subtle::ConstantTimeLess`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html
+///
+///```
+/// use subtle::ConstantTimeLess;
+/// use subtle_derive::{ConstantTimeGreater, ConstantTimeLess};
+///
+/// #[derive(ConstantTimeGreater, ConstantTimeLess)]
+/// struct S { x: u8, y: u8 }
+/// let s1 = S { x: 0, y: 2 };
+/// let s2 = S { x: 0, y: 1 };
+/// assert!(bool::from(!s1.ct_lt(&s1)));
+/// assert!(bool::from(s2.ct_lt(&s1)));
+///
+/// #[derive(ConstantTimeGreater, ConstantTimeLess)]
+/// struct T(u8, u8);
+/// let t1 = T(0, 2);
+/// let t2 = T(0, 1);
+/// assert!(bool::from(!t1.ct_lt(&t1)));
+/// assert!(bool::from(t2.ct_lt(&t1)));
+///
+/// /* ConstantTimeGreater does not need to be derived. */
+/// #[derive(ConstantTimeLess)]
+/// struct U(u8, u8);
+/// impl subtle::ConstantTimeGreater for U {
+///   fn ct_gt(&self, other: &Self) -> subtle::Choice { other.ct_lt(self) }
+/// }
+/// let u1 = U(1, 0);
+/// let u2 = U(0, 3);
+/// assert!(bool::from(u2.ct_lt(&u1)));
+/// assert!(bool::from(!u1.ct_lt(&u2)));
+///```
+#[proc_macro_derive(ConstantTimeLess, attributes(ct))]
+pub fn derive_lt(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let attrs = container_attrs(&attrs);
+  if attrs.derive_ct.partial_ord {
+    Code::ConflictingOptions.panic(
+      "ConstantTimeLess is already generated by ConstPartialOrd with #[ct(partial_ord(derive_ct))]",
+    );
+  }
+
+  let ct_impl = ct_lt_impl(&ident, &generics, &attrs, &data);
+  /* Compare the archived form of the type too, if requested. */
+  let archived_impl = if attrs.archived {
+    ct_lt_impl(
+      &archived_ident(&ident),
+      &generics,
+      &attrs,
+      &archived_data(&data),
+    )
+  } else {
+    quote! {}
+  };
+
+  let output = quote! {
+    #ct_impl
+    #archived_impl
+  };
+
+  with_subtle_path(output, attrs.krate.as_ref())
+}
+
+/* Generate a ct_cmp() implementation from the component constant-time traits. */
+fn ct_ord_impl(ident: &Ident, generics: &Generics) -> proc_macro2::TokenStream {
+  let generics = bounded_generics(generics, quote!(::subtle::ConstantTimeOrd));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+  /* The locals are hygienic, so that no user code expanded alongside them can refer to them. */
+  let [ret, is_eq, is_gt, is_lt] =
+    ["ret", "is_eq", "is_gt", "is_lt"].map(|name| Ident::new(name, Span::mixed_site()));
+  let derived = derived_attrs();
+  quote! {
+    #derived
+    impl #impl_generics ::subtle::ConstantTimeOrd for #ident #ty_generics #where_clause {
+      #[inline]
+      fn ct_cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+        let #is_gt = ::subtle::ConstantTimeGreater::ct_gt(self, other);
+        let #is_lt = ::subtle::ConstantTimeLess::ct_lt(self, other);
+        /* Check that the component traits agree with each other, without affecting release
+         * timing. */
+        #[cfg(debug_assertions)]
+        {
+          let #is_eq = ::subtle::ConstantTimeEq::ct_eq(self, other);
+          ::core::debug_assert_eq!(
+            #is_eq.unwrap_u8() + #is_gt.unwrap_u8() + #is_lt.unwrap_u8(),
+            1,
+            "exactly one of ct_eq(), ct_gt(), and ct_lt() must hold"
+          );
+        }
+        let mut #ret: i8 = 0;
+        ::subtle::ConditionallySelectable::conditional_assign(&mut #ret, &1, #is_gt);
+        ::subtle::ConditionallySelectable::conditional_assign(&mut #ret, &-1, #is_lt);
+        ::core::cmp::Ord::cmp(&#ret, &0)
+      }
+    }
+  }
+}
+
+/// Derive macro for
+/// [`subtle::ConstantTimeOrd`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeOrd.html).
+///
+/// [`subtle::ConstantTimeOrd`] is a marker trait over [`subtle::ConstantTimeEq`],
+/// [`subtle::ConstantTimeGreater`], and [`subtle::ConstantTimeLess`], so this macro requires those
+/// to be implemented as well. The generated `ct_cmp()` combines `ct_gt()` and `ct_lt()` without
+/// branching, and in debug builds additionally asserts that exactly one of `ct_eq()`, `ct_gt()`,
+/// and `ct_lt()` holds for the pair of operands.
+///
+/// [`subtle::ConstantTimeOrd`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeOrd.html
+/// [`subtle::ConstantTimeEq`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html
+/// [`subtle::ConstantTimeGreater`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html
+/// [`subtle::ConstantTimeLess`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html
+///
+///```
+/// use core::cmp::Ordering;
+/// use subtle::ConstantTimeOrd;
+/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd};
+///
+/// #[derive(ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd)]
+/// struct S { x: u8, y: u8 }
+/// let s1 = S { x: 0, y: 1 };
+/// let s2 = S { x: 0, y: 2 };
+/// assert_eq!(s1.ct_cmp(&s1), Ordering::Equal);
+/// assert_eq!(s1.ct_cmp(&s2), Ordering::Less);
+/// assert_eq!(s2.ct_cmp(&s1), Ordering::Greater);
+///```
+#[proc_macro_derive(ConstantTimeOrd)]
+pub fn derive_ct_ord(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    ..
+  } = parse_macro_input!(input);
+  if container_attrs(&attrs).derive_ct.ord {
+    Code::ConflictingOptions
+      .panic("ConstantTimeOrd is already generated by ConstOrd with #[ct(ord(derive_ct))]");
+  }
+
+  with_subtle_path(ct_ord_impl(&ident, &generics), None)
+}
+
+/// Implement [`PartialOrd`] given a [`subtle::ConstantTimePartialOrd`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimePartialOrd.html) implementation.
+///
+/// With `#[ct(partial_ord(derive_ct))]`, this also generates the [`subtle::ConstantTimeEq`],
+/// [`subtle::ConstantTimeGreater`] and [`subtle::ConstantTimeLess`] impls it relies on, as if
+/// [`ConstantTimeEq`](derive@ConstantTimeEq), [`ConstantTimeGreater`](derive@ConstantTimeGreater)
+/// and [`ConstantTimeLess`](derive@ConstantTimeLess) had been derived, so none of those may be
+/// derived as well. Along with `#[ct(ord(derive_ct))]` for [`ConstOrd`](derive@ConstOrd), a fully
+/// ordered type then only needs the three derives [`ConstEq`](derive@ConstEq), `ConstPartialOrd`
+/// and [`ConstOrd`](derive@ConstOrd).
+///
+/// [`subtle::ConstantTimeEq`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html
+/// [`subtle::ConstantTimeGreater`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html
+/// [`subtle::ConstantTimeLess`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html
+///
+///```
+/// use core::cmp::Ordering;
+/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd, ConstOrd};
+///
+/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd)]
+/// pub struct S(pub u8);
+///
+/// assert!(S(0) == S(0));
+/// assert!(S(0).partial_cmp(&S(0)) == Some(Ordering::Equal));
+/// assert!(S(0).partial_cmp(&S(1)) == Some(Ordering::Less));
+///
+/// #[derive(Debug, ConstEq, ConstPartialOrd, ConstOrd)]
+/// #[ct(partial_ord(derive_ct), ord(derive_ct))]
+/// pub struct T { x: u8, y: u8 }
+///
+/// assert!(T { x: 0, y: 1 } == T { x: 0, y: 1 });
+/// assert!(T { x: 0, y: 1 } < T { x: 0, y: 2 });
+/// assert!(T { x: 1, y: 0 }.cmp(&T { x: 0, y: 2 }) == Ordering::Greater);
+///```
+#[proc_macro_derive(ConstPartialOrd, attributes(ct))]
+pub fn derive_partial_ord(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  let attrs = container_attrs(&attrs);
+
+  /* Generate the ConstantTimeEq, ConstantTimeGreater and ConstantTimeLess impls too, if
+   * requested. */
+  let ct_impls = if attrs.derive_ct.partial_ord {
+    let eq_impl = ct_eq_impl(&ident, &generics, &attrs, &data);
+    let gt_impl = ct_gt_impl(&ident, &generics, &attrs, &data);
+    let lt_impl = ct_lt_impl(&ident, &generics, &attrs, &data);
+    quote! {
+      #eq_impl
+      #gt_impl
+      #lt_impl
+    }
+  } else {
+    quote! {}
+  };
+
+  let partial_ord_generics = bounded_generics(&generics, quote!(::subtle::ConstantTimeOrd));
+  let (impl_generics, ty_generics, where_clause) = partial_ord_generics.split_for_impl();
+
+  let derived = derived_attrs();
+  let output = quote! {
+    #ct_impls
+
+    #derived
+    impl #impl_generics ::core::cmp::PartialOrd for #ident #ty_generics #where_clause {
+      fn partial_cmp(&self, other: &Self) -> ::core::option::Option<::core::cmp::Ordering> {
+        ::core::convert::Into::into(::subtle::ConstantTimePartialOrd::ct_partial_cmp(self, other))
+      }
+    }
+  };
+
+  with_subtle_path(output, attrs.krate.as_ref())
+}
+
+/// Implement [`Ord`] given a [`subtle::ConstantTimeOrd`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeOrd.html) implementation.
+///
+/// With `#[ct(ord(derive_ct))]`, this also generates the [`subtle::ConstantTimeOrd`] impl, as if
+/// [`ConstantTimeOrd`](derive@ConstantTimeOrd) had been derived, so that derive may not be used as
+/// well.
+///
+/// [`subtle::ConstantTimeOrd`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeOrd.html
+///
+///```
+/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd, ConstEq, ConstPartialOrd, ConstOrd};
+///
+/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd, ConstEq, ConstPartialOrd, ConstOrd)]
+/// pub struct S(pub u8);
+///
+/// assert!(S(0) == S(0));
+/// assert!(S(0) < S(1));
+/// assert!(S(0) <= S(1));
+///
+/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd, ConstOrd)]
+/// #[ct(ord(derive_ct))]
+/// pub struct T(pub u8);
+///
+/// assert!(T(0) < T(1));
+/// assert!(T(1).max(T(2)) == T(2));
+///```
+#[proc_macro_derive(ConstOrd, attributes(ct))]
+pub fn derive_ord(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    ..
+  } = parse_macro_input!(input);
+  let ContainerAttrs {
+    derive_ct, krate, ..
+  } = container_attrs(&attrs);
+
+  /* Generate the ConstantTimeOrd impl too, if requested. */
+  let ct_impl = if derive_ct.ord {
+    ct_ord_impl(&ident, &generics)
+  } else {
+    quote! {}
+  };
+
+  let ord_generics = bounded_generics(&generics, quote!(::subtle::ConstantTimeOrd));
+  let (impl_generics, ty_generics, where_clause) = ord_generics.split_for_impl();
+
+  let derived = derived_attrs();
+  let output = quote! {
+    #ct_impl
+
+    #derived
+    impl #impl_generics ::core::cmp::Ord for #ident #ty_generics #where_clause {
+      fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+        ::subtle::ConstantTimeOrd::ct_cmp(self, other)
+      }
+    }
+  };
+
+  with_subtle_path(output, krate.as_ref())
+}
+
+/// Implement [`Neg`](core::ops::Neg) for both the type and references to it by negating every
+/// field.
+///
+/// This makes the blanket
+/// [`subtle::ConditionallyNegatable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallyNegatable.html)
+/// impl apply to any type which also implements
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html).
+/// Every field type `T` must implement `Neg<Output = T>` for `&T`.
+///
+///```
+/// use subtle::{Choice, ConditionallyNegatable, ConditionallySelectable};
+/// use subtle_derive::ConstNeg;
+///
+/// #[derive(Debug, Clone, Copy, PartialEq, ConstNeg)]
+/// struct P { x: i64, y: i64 }
+/// impl ConditionallySelectable for P {
+///   fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+///     P {
+///       x: i64::conditional_select(&a.x, &b.x, choice),
+///       y: i64::conditional_select(&a.y, &b.y, choice),
+///     }
+///   }
+/// }
+///
+/// assert_eq!(-P { x: 1, y: -2 }, P { x: -1, y: 2 });
+/// let mut p = P { x: 1, y: -2 };
+/// p.conditional_negate(Choice::from(0));
+/// assert_eq!(p, P { x: 1, y: -2 });
+/// p.conditional_negate(Choice::from(1));
+/// assert_eq!(p, P { x: -1, y: 2 });
+///
+/// #[derive(Debug, PartialEq, ConstNeg)]
+/// struct T(i32, i32);
+/// assert_eq!(-&T(3, -4), T(-3, 4));
+///
+/// #[derive(Debug, PartialEq, ConstNeg)]
+/// struct Pair<N> { a: N, b: N }
+/// assert_eq!(-Pair { a: 1i8, b: -2 }, Pair { a: -1, b: 2 });
+///```
+#[proc_macro_derive(ConstNeg)]
+pub fn derive_neg(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    data,
+    ..
+  } = parse_macro_input!(input);
+
+  /* Construct a new instance with every field negated. Numeric field names are valid in struct
+   * expressions, so this also works for tuple structs. */
+  let fields = struct_fields(&data);
+  let neg_expr = {
+    let names: Vec<&Member> = fields.iter().map(|(name, _)| name).collect();
+    let cfgs = field_cfgs(&fields);
+    quote! { #ident { #(#cfgs #names: -&self.#names),* } }
+  };
+
+  /* Every field must be negatable by reference, which only needs to be stated for generic
+   * types. */
+  let mut generics = generics;
+  if !generics.params.is_empty() {
+    let where_clause = generics.make_where_clause();
+    for (_, field) in &fields {
+      let ty = &field.ty;
+      where_clause
+        .predicates
+        .push(parse_quote!(for<'neg> &'neg #ty: ::core::ops::Neg<Output = #ty>));
+    }
+  }
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let derived = derived_attrs();
+  let output = quote! {
+    #derived
+    impl #impl_generics ::core::ops::Neg for &#ident #ty_generics #where_clause {
+      type Output = #ident #ty_generics;
+
+      #[inline]
+      fn neg(self) -> Self::Output {
+        #neg_expr
+      }
+    }
+
+    #derived
+    impl #impl_generics ::core::ops::Neg for #ident #ty_generics #where_clause {
+      type Output = Self;
+
+      #[inline]
+      fn neg(self) -> Self {
+        -&self
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/// Generate constant-time `ct_min()`, `ct_max()`, and `ct_clamp()` methods.
+///
+/// These select between values with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html)
+/// according to the results of
+/// [`subtle::ConstantTimeGreater`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html)
+/// and
+/// [`subtle::ConstantTimeLess`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html),
+/// which must all be implemented for the type. When both values are equal, `ct_min()` and
+/// `ct_max()` return `self`.
+///
+///```
+/// use subtle::{Choice, ConditionallySelectable};
+/// use subtle_derive::{ConstantTimeGreater, ConstantTimeLess, CtMinMax};
+///
+/// #[derive(Debug, Clone, Copy, PartialEq, ConstantTimeGreater, ConstantTimeLess, CtMinMax)]
+/// struct S(u8);
+/// impl ConditionallySelectable for S {
+///   fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+///     S(u8::conditional_select(&a.0, &b.0, choice))
+///   }
+/// }
+///
+/// assert_eq!(S(1).ct_min(&S(2)), S(1));
+/// assert_eq!(S(1).ct_max(&S(2)), S(2));
+/// assert_eq!(S(0).ct_clamp(&S(3), &S(5)), S(3));
+/// assert_eq!(S(4).ct_clamp(&S(3), &S(5)), S(4));
+/// assert_eq!(S(9).ct_clamp(&S(3), &S(5)), S(5));
+///```
+#[proc_macro_derive(CtMinMax)]
+pub fn derive_min_max(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident, generics, ..
+  } = parse_macro_input!(input);
+  let generics = self_bounded_generics(
+    &ident,
+    &generics,
+    quote!(
+      ::subtle::ConstantTimeGreater
+        + ::subtle::ConstantTimeLess
+        + ::subtle::ConditionallySelectable
+    ),
+  );
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Return the lesser of `self` and `other`, in constant time.
+      #[inline]
+      #[must_use]
+      pub fn ct_min(&self, other: &Self) -> Self {
+        let other_is_less = ::subtle::ConstantTimeGreater::ct_gt(self, other);
+        ::subtle::ConditionallySelectable::conditional_select(self, other, other_is_less)
+      }
+
+      /// Return the greater of `self` and `other`, in constant time.
+      #[inline]
+      #[must_use]
+      pub fn ct_max(&self, other: &Self) -> Self {
+        let other_is_greater = ::subtle::ConstantTimeLess::ct_lt(self, other);
+        ::subtle::ConditionallySelectable::conditional_select(self, other, other_is_greater)
+      }
+
+      /// Restrict `self` to lie within `lo..=hi`, in constant time.
+      #[inline]
+      #[must_use]
+      pub fn ct_clamp(&self, lo: &Self, hi: &Self) -> Self {
+        self.ct_max(lo).ct_min(hi)
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/// Generate a constant-time `ct_contains()` membership test.
+///
+/// The generated function compares the needle against every element of the haystack with
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html) and
+/// ORs the results together, so it always scans the entire slice instead of exiting early at a
+/// match. Only the length of the haystack affects its timing.
+///
+///```
+/// use subtle_derive::{ConstantTimeEq, CtContains};
+///
+/// #[derive(ConstantTimeEq, CtContains)]
+/// struct Code([u8; 2]);
+///
+/// let allowed = [Code(*b"ab"), Code(*b"cd"), Code(*b"ef")];
+/// assert!(bool::from(Code::ct_contains(&allowed, &Code(*b"cd"))));
+/// assert!(bool::from(!Code::ct_contains(&allowed, &Code(*b"gh"))));
+/// assert!(bool::from(!Code::ct_contains(&[], &Code(*b"ab"))));
+///
+/// #[derive(ConstantTimeEq, CtContains)]
+/// struct Id<T>(T);
+/// assert!(bool::from(Id::ct_contains(&[Id(1u32), Id(2)], &Id(2))));
+///```
+#[proc_macro_derive(CtContains)]
+pub fn derive_contains(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident, generics, ..
+  } = parse_macro_input!(input);
+  let generics = self_bounded_generics(&ident, &generics, quote!(::subtle::ConstantTimeEq));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Check whether `needle` is equal to any element of `haystack`, scanning every element.
+      #[must_use]
+      pub fn ct_contains(haystack: &[Self], needle: &Self) -> ::subtle::Choice {
+        let mut found = <::subtle::Choice as ::core::convert::From<u8>>::from(0u8);
+        for candidate in haystack {
+          found |= ::subtle::ConstantTimeEq::ct_eq(candidate, needle);
+        }
+        found
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/// Generate a constant-time `ct_lookup()` table index.
+///
+/// The generated function reads every entry of the table and conditionally assigns the one at
+/// `index` with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html),
+/// so the memory access pattern does not depend on the (secret) index. The type must also
+/// implement [`Default`], which is returned if `index` is out of bounds.
+///
+///```
+/// use subtle::{Choice, ConditionallySelectable};
+/// use subtle_derive::CtLookup;
+///
+/// #[derive(Debug, Default, Clone, Copy, PartialEq, CtLookup)]
+/// struct Point { x: u32, y: u32 }
+/// impl ConditionallySelectable for Point {
+///   fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+///     Point {
+///       x: u32::conditional_select(&a.x, &b.x, choice),
+///       y: u32::conditional_select(&a.y, &b.y, choice),
+///     }
+///   }
+/// }
+///
+/// let table = [Point { x: 1, y: 2 }, Point { x: 3, y: 4 }, Point { x: 5, y: 6 }];
+/// assert_eq!(Point::ct_lookup(&table, &1), Point { x: 3, y: 4 });
+/// assert_eq!(Point::ct_lookup(&table, &3), Point::default());
+///```
+#[proc_macro_derive(CtLookup)]
+pub fn derive_lookup(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident, generics, ..
+  } = parse_macro_input!(input);
+  let generics = self_bounded_generics(
+    &ident,
+    &generics,
+    quote!(::core::default::Default + ::subtle::ConditionallySelectable),
+  );
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Select `table[*index]` while touching every entry, or the default value if `index` is
+      /// out of bounds.
+      #[must_use]
+      pub fn ct_lookup(table: &[Self], index: &usize) -> Self {
+        let mut ret: Self = ::core::default::Default::default();
+        for (i, entry) in ::core::iter::Iterator::enumerate(table.iter()) {
+          let is_match = ::subtle::ConstantTimeEq::ct_eq(&i, index);
+          ::subtle::ConditionallySelectable::conditional_assign(&mut ret, entry, is_match);
+        }
+        ret
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/// Generate a constant-time `ct_sort()` for fixed-size arrays.
+///
+/// The generated function sorts in ascending order with an odd-even transposition network: `N`
+/// rounds of compare-and-swap over alternating pairs of adjacent elements, where each swap is
+/// performed with `conditional_swap()` according to `ct_gt()`. The sequence of comparisons depends
+/// only on `N`, so this performs `O(N^2)` comparisons and is intended for small arrays. The type
+/// must implement
+/// [`subtle::ConstantTimeGreater`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html)
+/// and
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html).
+///
+///```
+/// use subtle::{Choice, ConditionallySelectable};
+/// use subtle_derive::{ConstantTimeGreater, CtSort};
+///
+/// #[derive(Debug, Clone, Copy, PartialEq, ConstantTimeGreater, CtSort)]
+/// struct S(u8, u8);
+/// impl ConditionallySelectable for S {
+///   fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+///     S(u8::conditional_select(&a.0, &b.0, choice), u8::conditional_select(&a.1, &b.1, choice))
+///   }
+/// }
+///
+/// let mut arr = [S(2, 0), S(0, 5), S(1, 1), S(0, 2), S(2, 0)];
+/// S::ct_sort(&mut arr);
+/// assert_eq!(arr, [S(0, 2), S(0, 5), S(1, 1), S(2, 0), S(2, 0)]);
+///```
+#[proc_macro_derive(CtSort)]
+pub fn derive_sort(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident, generics, ..
+  } = parse_macro_input!(input);
+  let generics = self_bounded_generics(
+    &ident,
+    &generics,
+    quote!(::subtle::ConstantTimeGreater + ::subtle::ConditionallySelectable),
+  );
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #lints
+    impl #impl_generics #ident #ty_generics #where_clause {
+      /// Sort `arr` in ascending order, with a sequence of operations that depends only on `N`.
+      pub fn ct_sort<const N: usize>(arr: &mut [Self; N]) {
+        for round in 0..N {
+          let mut i = round % 2;
+          while i + 1 < N {
+            let (lo, hi) = arr.split_at_mut(i + 1);
+            let (a, b) = (&mut lo[i], &mut hi[0]);
+            let out_of_order = ::subtle::ConstantTimeGreater::ct_gt(&*a, &*b);
+            ::subtle::ConditionallySelectable::conditional_swap(a, b, out_of_order);
+            i += 2;
+          }
+        }
+      }
+    }
+  };
+
+  with_subtle_path(output, None)
+}
+
+/* Generate a test comparing every pair of values of a type, built from every combination of the
+ * values in the domains of its fields. */
+fn exhaustive_test(ident: &Ident, data: &Data, tests: &CtTests) -> proc_macro2::TokenStream {
+  let fields = struct_fields(data);
+  let mut values: Vec<Ident> = Vec::new();
+  let mut domains: Vec<proc_macro2::TokenStream> = Vec::new();
+  for (i, (member, field)) in fields.iter().enumerate() {
+    if !cfg_attrs(Some(field)).is_empty() {
+      Code::UnsupportedCfgField.panic("#[ct(test(exhaustive))] doesn't support #[cfg(...)] fields");
+    }
+    let ty = &field.ty;
+    domains.push(match field_attrs(&field.attrs).domain {
+      Some(domain) => quote!(#domain),
+      None if is_named_type(ty, "bool") => quote!([false, true]),
+      None if is_named_type(ty, "u8") || is_named_type(ty, "i8") => {
+        quote!(::core::primitive::#ty::MIN..=::core::primitive::#ty::MAX)
+      },
+      None => Code::UnsupportedFieldType.panic(format_args!(
+        "field {} needs #[ct(domain = \"...\")] for #[ct(test(exhaustive))], since only u8, i8 \
+         and bool fields have a default domain",
+        quote!(#member)
+      )),
+    });
+    values.push(format_ident!("value_{}", i));
+  }
+  let members: Vec<&Member> = fields.iter().map(|(member, _)| member).collect();
+
+  /* Build every value in nested loops, innermost for the last field. */
+  let mut build = quote! {
+    values.push(super::#ident { #(#members: #values),* });
+  };
+  for (value, domain) in values.iter().zip(&domains).rev() {
+    build = quote! {
+      for #value in #domain {
+        #build
+      }
+    };
+  }
+
+  let expected = Ident::new("expected", Span::mixed_site());
+  let checks = field_eq_stmts(data, quote!(a), quote!(b), &expected);
+  let mut asserts = vec![quote! {
+    ::core::assert_eq!(
+      ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(a, b)),
+      #expected,
+      "ct_eq() disagrees with comparing each field with =="
+    );
+  }];
+  if tests.partial_eq {
+    asserts.push(quote! {
+      ::core::assert_eq!(a == b, #expected, "== disagrees with comparing each field with ==");
+    });
+  }
+  if tests.ord || tests.cmp {
+    asserts.push(quote! {
+      let ordering = ::core::cmp::Ordering::Equal;
+      #(
+        let ordering = ::core::cmp::Ordering::then(ordering, ::core::cmp::Ord::cmp(&a.#members, &b.#members));
+      )*
+    });
+  }
+  if tests.ord {
+    asserts.push(quote! {
+      ::core::assert_eq!(
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeGreater::ct_gt(a, b)),
+        ordering == ::core::cmp::Ordering::Greater,
+        "ct_gt() disagrees with comparing each field in order"
+      );
+      ::core::assert_eq!(
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeLess::ct_lt(a, b)),
+        ordering == ::core::cmp::Ordering::Less,
+        "ct_lt() disagrees with comparing each field in order"
+      );
+    });
+  }
+  if tests.cmp {
+    asserts.push(quote! {
+      ::core::assert_eq!(
+        ::subtle::ConstantTimeOrd::ct_cmp(a, b),
+        ordering,
+        "ct_cmp() disagrees with comparing each field in order"
+      );
+    });
+  }
+
+  quote! {
+    #[test]
+    fn ct_exhaustive() {
+      /* Resolve the domains from the module of the type. */
+      #[allow(unused_imports)]
+      use super::*;
+      let mut values = ::std::vec::Vec::new();
+      #build
+      for a in &values {
+        for b in &values {
+          #[allow(unused_mut)]
+          let mut #expected = true;
+          #(#checks)*
+          #(#asserts)*
+        }
+      }
+    }
+  }
+}
+
+/* Generate an ignored test which times `ct_eq()` on a pair of equal values and on a pair of values
+ * which differ as much as possible, in a random order, and fails if Welch's t-test finds that the
+ * mean times differ. Each measurement times a batch of calls, since a single call is usually too
+ * quick for the clock, and the slowest 5% of each class are discarded as noise. */
+fn timing_test_fn(ident: &Ident, timing: &TimingTest) -> proc_macro2::TokenStream {
+  let TimingTest {
+    equal,
+    different,
+    samples,
+  } = timing;
+  quote! {
+    #[test]
+    #[ignore]
+    fn ct_eq_timing() {
+      /* Resolve the generators from the module of the type. */
+      #[allow(unused_imports)]
+      use super::*;
+      const BATCH: usize = 64;
+      /* The threshold used by dudect, beyond which a leak is very likely. */
+      const THRESHOLD: f64 = 4.5;
+      let inputs: [(super::#ident, super::#ident); 2] = [#equal(), #different()];
+      let mut times: [::std::vec::Vec<f64>; 2] = [::std::vec::Vec::new(), ::std::vec::Vec::new()];
+      /* Choose each class with a fixed xorshift sequence, so the order can't correlate with any
+       * periodic noise. */
+      let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+      while times[0].len() < #samples || times[1].len() < #samples {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let class = (state & 1) as usize;
+        let (a, b) = &inputs[class];
+        let start = ::std::time::Instant::now();
+        for _ in 0..BATCH {
+          ::core::hint::black_box(::subtle::ConstantTimeEq::ct_eq(
+            ::core::hint::black_box(a),
+            ::core::hint::black_box(b),
+          ));
+        }
+        times[class].push(start.elapsed().as_nanos() as f64);
+      }
+      let mut stats = [(0.0, 0.0, 0.0); 2];
+      for (class, times) in times.iter_mut().enumerate() {
+        times.sort_by(f64::total_cmp);
+        times.truncate(times.len() - times.len() / 20);
+        let n = times.len() as f64;
+        let mean = ::core::iter::Iterator::sum::<f64>(times.iter()) / n;
+        let variance = ::core::iter::Iterator::sum::<f64>(::core::iter::Iterator::map(
+          times.iter(),
+          |time| (time - mean) * (time - mean),
+        )) / (n - 1.0);
+        stats[class] = (n, mean, variance);
+      }
+      let [(n0, mean0, variance0), (n1, mean1, variance1)] = stats;
+      let error = (variance0 / n0 + variance1 / n1).sqrt();
+      let t = if error > 0.0 { (mean0 - mean1) / error } else { 0.0 };
+      ::core::assert!(
+        t.abs() < THRESHOLD,
+        "ct_eq() takes different times for equal and different inputs: t = {:.2}, means {:.1}ns \
+         and {:.1}ns per {} calls",
+        t,
+        mean0,
+        mean1,
+        BATCH
+      );
+    }
+  }
+}
+
+/// Generate property-based tests checking that the constant-time trait impls of a type are
+/// consistent with each other.
+///
+/// This generates a `#[cfg(test)]` module named after the type, as `<type>_ct_test`, containing
+/// [`proptest`](https://docs.rs/proptest) tests checking that `ct_eq()` is reflexive and symmetric.
+/// Further properties are checked with `#[ct(test(...))]`:
+/// - `partial_eq`: `ct_eq()` agrees with `==`, as generated by [`ConstEq`](derive@ConstEq).
+/// - `ord`: exactly one of `ct_eq()`, `ct_gt()` and `ct_lt()` holds, and `a.ct_gt(&b)` agrees
+///   with `b.ct_lt(&a)`.
+/// - `cmp`: `ct_cmp()` agrees with `ct_gt()` and `ct_lt()`.
+///
+/// The type must implement `proptest::arbitrary::Arbitrary` and [`Debug`], and the crate must have
+/// `proptest` as a dev-dependency.
+///
+/// With `#[ct(test(timing(equal = "...", different = "...")))]`, an `#[ignore]`d test named
+/// `ct_eq_timing` is also generated, which is a coarse check for timing leaks that needs no harness
+/// beyond `cargo test`, run on the target hardware with
+/// `cargo test --release -- --ignored ct_eq_timing`. The functions named by `equal` and `different`
+/// take no arguments and return a pair of values of the type, which are respectively equal and as
+/// different as possible, such as differing in their first byte. The test times batches of calls to
+/// `ct_eq()` on each pair, in a random order, and fails if Welch's t-test finds a significant
+/// difference between the mean times of the two classes, with the same threshold of `|t| < 4.5` as
+/// dudect. It takes `samples = N` measurements of each class, 10,000 by default. For a more thorough
+/// check, see [`CtVerify`](derive@CtVerify). The timing test doesn't use `proptest`, so with
+/// `#[ct(test(timing(...)))]` alone, only the timing test is generated, and the type needn't
+/// implement `proptest::arbitrary::Arbitrary` or [`Debug`].
+///
+/// For types with few enough values, `#[ct(test(exhaustive))]` instead generates a single test
+/// comparing every pair of values, which needs neither `proptest` nor `Debug`. It checks that
+/// `ct_eq()` holds exactly when every field compares equal with `==`, and with `ord` or `cmp`, that
+/// the orderings agree with comparing the fields with [`Ord`] one after another. `u8`, `i8` and
+/// `bool` fields take all of their values, and other fields must list theirs with
+/// `#[ct(domain = "...")]`, as an expression which can be iterated over, such as an array of enum
+/// variants. The number of pairs is the square of the product of the sizes of the domains, so this
+/// is only feasible for types with at most a few thousand values.
+///
+/// Unlike the output of the other derives, the module is not
+/// emitted within an anonymous scope, since test functions must be nameable by the test harness.
+/// Generic types are not supported, since each test needs a concrete type to generate values of.
+///
+///```
+/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, CtTest};
+///
+/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, CtTest)]
+/// #[ct(test(partial_eq, ord, timing(equal = "equal_keys", different = "different_keys")))]
+/// struct Key { id: u32, bytes: [u8; 4] }
+///
+/// fn equal_keys() -> (Key, Key) {
+///   (Key { id: 1, bytes: [0; 4] }, Key { id: 1, bytes: [0; 4] })
+/// }
+///
+/// fn different_keys() -> (Key, Key) {
+///   (Key { id: 1, bytes: [0; 4] }, Key { id: !1, bytes: [!0; 4] })
+/// }
+///
+/// #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// enum Level { Low, High }
+///
+/// impl subtle::ConstantTimeEq for Level {
+///   fn ct_eq(&self, other: &Self) -> subtle::Choice {
+///     subtle::ConstantTimeEq::ct_eq(&(*self as u8), &(*other as u8))
+///   }
+/// }
+///
+/// #[derive(ConstantTimeEq, CtTest)]
+/// #[ct(test(exhaustive))]
+/// struct Flags { mask: u8, on: bool, #[ct(domain = "[Level::Low, Level::High]")] level: Level }
+///```
+#[proc_macro_derive(CtTest, attributes(ct))]
+pub fn derive_test(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    generics,
+    attrs,
+    data,
+    ..
+  } = parse_macro_input!(input);
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("CtTest can't be derived for generic types");
+  }
+  let ContainerAttrs { krate, tests, .. } = container_attrs(&attrs);
+  let module = format_ident!(
+    "{}_ct_test",
+    ident.unraw().to_string().to_lowercase(),
+    span = ident.span()
+  );
+  let ty = quote!(super::#ident);
+  let any = quote!(::proptest::arbitrary::any::<#ty>());
+
+  let mut props: Vec<proc_macro2::TokenStream> = vec![quote! {
+    #[test]
+    fn ct_eq_reflexive(a in #any) {
+      ::proptest::prop_assert!(::core::convert::Into::<bool>::into(
+        ::subtle::ConstantTimeEq::ct_eq(&a, &a)
+      ));
+    }
+
+    #[test]
+    fn ct_eq_symmetric(a in #any, b in #any) {
+      ::proptest::prop_assert_eq!(
+        ::subtle::ConstantTimeEq::ct_eq(&a, &b).unwrap_u8(),
+        ::subtle::ConstantTimeEq::ct_eq(&b, &a).unwrap_u8()
+      );
+    }
+  }];
+  if tests.partial_eq {
+    props.push(quote! {
+      #[test]
+      fn ct_eq_matches_partial_eq(a in #any, b in #any) {
+        ::proptest::prop_assert_eq!(
+          a == b,
+          ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(&a, &b))
+        );
+      }
+    });
+  }
+  if tests.ord {
+    props.push(quote! {
+      #[test]
+      fn ct_ord_trichotomy(a in #any, b in #any) {
+        let eq = ::subtle::ConstantTimeEq::ct_eq(&a, &b).unwrap_u8();
+        let gt = ::subtle::ConstantTimeGreater::ct_gt(&a, &b).unwrap_u8();
+        let lt = ::subtle::ConstantTimeLess::ct_lt(&a, &b).unwrap_u8();
+        ::proptest::prop_assert_eq!(eq + gt + lt, 1u8);
+        ::proptest::prop_assert_eq!(gt, ::subtle::ConstantTimeLess::ct_lt(&b, &a).unwrap_u8());
+      }
+
+      #[test]
+      fn ct_ord_irreflexive(a in #any) {
+        ::proptest::prop_assert_eq!(::subtle::ConstantTimeGreater::ct_gt(&a, &a).unwrap_u8(), 0u8);
+        ::proptest::prop_assert_eq!(::subtle::ConstantTimeLess::ct_lt(&a, &a).unwrap_u8(), 0u8);
+      }
+    });
+  }
+  if tests.cmp {
+    props.push(quote! {
+      #[test]
+      fn ct_cmp_matches_gt_lt(a in #any, b in #any) {
+        let gt = ::core::convert::Into::<bool>::into(::subtle::ConstantTimeGreater::ct_gt(&a, &b));
+        let lt = ::core::convert::Into::<bool>::into(::subtle::ConstantTimeLess::ct_lt(&a, &b));
+        let expected = match (gt, lt) {
+          (true, _) => ::core::cmp::Ordering::Greater,
+          (_, true) => ::core::cmp::Ordering::Less,
+          _ => ::core::cmp::Ordering::Equal,
+        };
+        ::proptest::prop_assert_eq!(::subtle::ConstantTimeOrd::ct_cmp(&a, &b), expected);
+      }
+    });
+  }
+
+  /* A type which only asks for the timing test needn't support proptest. */
+  let timing_only = tests.timing.is_some() && !(tests.partial_eq || tests.ord || tests.cmp);
+  let body = if tests.exhaustive {
+    exhaustive_test(&ident, &data, &tests)
+  } else if timing_only {
+    quote! {}
+  } else {
+    quote! {
+      ::proptest::proptest! {
+        #(#props)*
+      }
+    }
+  };
+  let timing = tests
+    .timing
+    .as_ref()
+    .map(|timing| timing_test_fn(&ident, timing));
+
+  let lints = allowed_lints();
+  let output = quote! {
+    #[cfg(test)]
+    #lints
+    mod #module {
+      #body
+      #timing
+    }
+  };
+
+  /* The module must stay outside any anonymous scope for its tests to be run, so this only
+   * rewrites its paths. */
+  paths::rewrite_subtle_paths(output, &paths::subtle_path(krate.as_ref())).into()
+}
+
+/* Generate an expression evaluating to `a` if `choice` is set and `b` otherwise. Every operand is
+ * evaluated exactly once, before any of them is bound to a local variable. */
+fn select_expr(choice: &Expr, a: &Expr, b: &Expr) -> proc_macro2::TokenStream {
+  let lints = allowed_lints();
+  quote! {
+    match (#choice, #a, #b) {
+      (choice, a, b) => {
+        /* Name the bound here so that a type without it produces a clear error. */
+        #[inline(always)]
+        #lints
+        fn ct_select<T: ::subtle::ConditionallySelectable>(
+          choice: ::subtle::Choice,
+          a: T,
+          b: T,
+        ) -> T {
+          T::conditional_select(&b, &a, choice)
+        }
+        ct_select(choice, a, b)
+      }
+    }
+  }
+}
+
+struct SelectInput {
+  choice: Expr,
+  a: Expr,
+  b: Expr,
+}
+
+impl Parse for SelectInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let choice = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let a = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let b = input.parse()?;
+    if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+    }
+    Ok(Self { choice, a, b })
+  }
+}
+
+/// Select between two values in constant time.
+///
+/// `ct_select!(choice, a, b)` evaluates to `a` if `choice` is set and to `b` otherwise, like
+/// `if choice { a } else { b }`, using
+/// [`subtle::ConditionallySelectable::conditional_select()`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html#tymethod.conditional_select).
+/// `choice` must be a [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html),
+/// and both `a` and `b` are always evaluated. The trait does not need to be imported.
+///
+///```
+/// use subtle::Choice;
+/// use subtle_derive::ct_select;
+///
+/// assert_eq!(ct_select!(Choice::from(1), 3u32, 4u32), 3);
+/// assert_eq!(ct_select!(Choice::from(0), 3u32, 4u32), 4);
+///```
+#[proc_macro]
+pub fn ct_select(input: TokenStream) -> TokenStream {
+  let SelectInput { choice, a, b } = parse_macro_input!(input);
+
+  select_expr(&choice, &a, &b).into()
+}
+
+struct IfInput {
+  cond: Expr,
+  then_expr: Expr,
+  else_expr: Expr,
+}
+
+impl Parse for IfInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let cond = input.parse()?;
+    input.parse::<Token![=>]>()?;
+    let then_expr = input.parse()?;
+    input.parse::<Token![,]>()?;
+    input.parse::<Token![else]>()?;
+    input.parse::<Token![=>]>()?;
+    let else_expr = input.parse()?;
+    if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+    }
+    Ok(Self {
+      cond,
+      then_expr,
+      else_expr,
+    })
+  }
+}
+
+/// Evaluate both arms of a conditional and select the result in constant time.
+///
+/// `ct_if!(cond => a, else => b)` reads like `if cond { a } else { b }`, but `cond` must be a
+/// [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html), both arms are
+/// always evaluated, and the result is chosen with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html)
+/// instead of a branch. This is otherwise equivalent to [`ct_select!`].
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ct_if;
+///
+/// let secret = 5u8;
+/// let x = ct_if!(secret.ct_eq(&5) => 10u64, else => 20u64);
+/// assert_eq!(x, 10);
+/// let y = ct_if!(secret.ct_eq(&6) => 10u64, else => 20u64);
+/// assert_eq!(y, 20);
+///```
+///
+/// Arms whose type does not implement `ConditionallySelectable` are rejected:
+///
+///```compile_fail
+/// use subtle::Choice;
+/// use subtle_derive::ct_if;
+///
+/// let s = ct_if!(Choice::from(1) => String::new(), else => String::new());
+///```
+#[proc_macro]
+pub fn ct_if(input: TokenStream) -> TokenStream {
+  let IfInput {
+    cond,
+    then_expr,
+    else_expr,
+  } = parse_macro_input!(input);
+
+  select_expr(&cond, &then_expr, &else_expr).into()
+}
+
+struct EqChainInput {
+  pairs: Vec<(Expr, Expr)>,
+}
+
+impl Parse for EqChainInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let mut pairs = Vec::new();
+    while !input.is_empty() {
+      let content;
+      parenthesized!(content in input);
+      let a = content.parse()?;
+      content.parse::<Token![,]>()?;
+      let b = content.parse()?;
+      if content.peek(Token![,]) {
+        content.parse::<Token![,]>()?;
+      }
+      pairs.push((a, b));
+      if !input.is_empty() {
+        input.parse::<Token![,]>()?;
+      }
+    }
+    Ok(Self { pairs })
+  }
+}
+
+/// Compare several pairs of values at once in constant time.
+///
+/// `ct_eq_chain!((a1, b1), (a2, b2), ...)` folds `a1.ct_eq(&b1)`, `a2.ct_eq(&b2)`, etc. through a
+/// single [`subtle::IteratedEq`](https://docs.rs/subtle/latest/subtle/struct.IteratedEq.html),
+/// or directly into a `Choice` with the `upstream-subtle` feature, and evaluates to a
+/// [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html) which is only set
+/// if every pair is equal. Each pair may have a different type, as long as it
+/// implements
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html).
+/// Every pair is compared regardless of the results of the others. With no pairs, the result is
+/// set.
+///
+///```
+/// use subtle_derive::ct_eq_chain;
+///
+/// let tag = [1u8, 2, 3, 4];
+/// let len = 16u32;
+/// let context = 7u8;
+/// assert!(bool::from(ct_eq_chain!((tag, [1u8, 2, 3, 4]), (len, 16), (context, 7))));
+/// assert!(bool::from(!ct_eq_chain!((tag, [1u8, 2, 3, 4]), (len, 17), (context, 7))));
+/// assert!(bool::from(ct_eq_chain!()));
+///```
+#[proc_macro]
+pub fn ct_eq_chain(input: TokenStream) -> TokenStream {
+  let EqChainInput { pairs } = parse_macro_input!(input);
+
+  /* Use a hygienic name for the accumulator, so it can't shadow anything in the operands. */
+  let ret = Ident::new("ret", Span::mixed_site());
+  let output = if cfg!(feature = "upstream-subtle") {
+    /* Fold the pairs into a `Choice` directly. */
+    let apply_stmts = pairs
+      .iter()
+      .flat_map(|(a, b)| folded_stmts("apply_eq", &quote!((#a)), &quote!((#b)), Span::call_site()));
+    quote! {
+      {
+        #[allow(unused_mut)]
+        let mut #ret = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8);
+        #(#apply_stmts)*
+        #ret
+      }
+    }
+  } else {
+    let apply_stmts = pairs.iter().map(|(a, b)| {
+      quote! { ::subtle::IteratedEq::apply_eq(&mut #ret, &(#a), &(#b)); }
+    });
+    quote! {
+      {
+        #[allow(unused_mut)]
+        let mut #ret = <::subtle::IteratedEq as ::subtle::IteratedOperation>::initiate();
+        #(#apply_stmts)*
+        ::subtle::IteratedOperation::extract_result(#ret)
+      }
+    }
+  };
+
+  output.into()
+}
+
+struct WrapperInput {
+  ty: Type,
+  path: Vec<Member>,
+  traits: Vec<Ident>,
+}
+
+impl Parse for WrapperInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let ty = input.parse()?;
+    input.parse::<Token![=>]>()?;
+    let mut path = Vec::new();
+    while input.peek(Token![.]) {
+      input.parse::<Token![.]>()?;
+      path.push(input.parse()?);
+    }
+    if path.is_empty() {
+      return Err(Code::InvalidMacroInput.error_at(
+        input.span(),
+        "expected a field access such as `.0` or `.inner`",
+      ));
+    }
+    let traits = if input.peek(Token![;]) {
+      input.parse::<Token![;]>()?;
+      punctuated::Punctuated::<Ident, Token![,]>::parse_terminated(input)?
+        .into_iter()
+        .collect()
+    } else {
+      Vec::new()
+    };
+    Ok(Self { ty, path, traits })
+  }
+}
+
+/// Implement the constant-time comparison traits for a newtype by delegating to an inner value.
+///
+/// `impl_constant_time_wrapper!(Wrapper => .0)` implements
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html),
+/// [`subtle::ConstantTimeGreater`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html),
+/// and
+/// [`subtle::ConstantTimeLess`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html)
+/// for `Wrapper` by comparing `self.0` against `other.0`. Any chain of field accesses may be used
+/// (e.g. `.inner.bytes`). To only implement some of the traits, e.g. when the inner type has no
+/// ordering, list them after a semicolon: `impl_constant_time_wrapper!(Wrapper => .0;
+/// ConstantTimeEq)`.
+///
+///```
+/// use subtle::{ConstantTimeEq, ConstantTimeGreater};
+/// use subtle_derive::impl_constant_time_wrapper;
+///
+/// /* Pretend this is a foreign type, with comparisons that we can't derive. */
+/// mod foreign { pub struct Counter { pub value: u64 } }
+///
+/// struct Wrapper(foreign::Counter);
+/// impl_constant_time_wrapper!(Wrapper => .0.value);
+///
+/// let w1 = Wrapper(foreign::Counter { value: 1 });
+/// let w2 = Wrapper(foreign::Counter { value: 2 });
+/// assert!(bool::from(w1.ct_eq(&w1)));
+/// assert!(bool::from(w2.ct_gt(&w1)));
+///
+/// struct Bytes([u8; 4]);
+/// impl_constant_time_wrapper!(Bytes => .0; ConstantTimeEq);
+/// assert!(bool::from(Bytes([1, 2, 3, 4]).ct_eq(&Bytes([1, 2, 3, 4]))));
+///```
+#[proc_macro]
+pub fn impl_constant_time_wrapper(input: TokenStream) -> TokenStream {
+  let WrapperInput { ty, path, traits } = parse_macro_input!(input);
+
+  let wants = |name: &str| traits.is_empty() || traits.iter().any(|t| t == name);
+  if let Some(unknown) = traits.iter().find(|t| {
+    !["ConstantTimeEq", "ConstantTimeGreater", "ConstantTimeLess"]
+      .iter()
+      .any(|name| *t == name)
+  }) {
+    Code::UnsupportedTrait.panic(format_args!(
+      "impl_constant_time_wrapper!() does not support the trait {}",
+      unknown
+    ));
+  }
+
+  let mut output = quote! {};
+  if wants("ConstantTimeEq") {
+    let derived = derived_attrs();
+    output.extend(quote! {
+      #derived
+      impl ::subtle::ConstantTimeEq for #ty {
+        #[inline]
+        fn ct_eq(&self, other: &Self) -> ::subtle::Choice {
+          ::subtle::ConstantTimeEq::ct_eq(&self #(.#path)*, &other #(.#path)*)
+        }
+      }
+    });
+  }
+  if wants("ConstantTimeGreater") {
+    let derived = derived_attrs();
+    output.extend(quote! {
+      #derived
+      impl ::subtle::ConstantTimeGreater for #ty {
+        #[inline]
+        fn ct_gt(&self, other: &Self) -> ::subtle::Choice {
+          ::subtle::ConstantTimeGreater::ct_gt(&self #(.#path)*, &other #(.#path)*)
+        }
+      }
+    });
+  }
+  if wants("ConstantTimeLess") {
+    let derived = derived_attrs();
+    output.extend(quote! {
+      #derived
+      impl ::subtle::ConstantTimeLess for #ty {
+        #[inline]
+        fn ct_lt(&self, other: &Self) -> ::subtle::Choice {
+          ::subtle::ConstantTimeLess::ct_lt(&self #(.#path)*, &other #(.#path)*)
+        }
+      }
+    });
+  }
+
+  anonymous_scope(output).into()
+}
+
+/// Swap two values in constant time if a condition is set.
+///
+/// `ct_swap!(choice, &mut a, &mut b);` swaps `a` and `b` if `choice` is set and leaves them alone
+/// otherwise, using
+/// [`subtle::ConditionallySelectable::conditional_swap()`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html#method.conditional_swap).
+/// Types which only implement `conditional_select()` are swapped by the trait's default
+/// `conditional_swap()`, which selects both values from temporary copies. The trait does not need
+/// to be imported.
+///
+///```
+/// use subtle::Choice;
+/// use subtle_derive::ct_swap;
+///
+/// let (mut a, mut b) = (1u32, 2u32);
+/// ct_swap!(Choice::from(0), &mut a, &mut b);
+/// assert_eq!((a, b), (1, 2));
+/// ct_swap!(Choice::from(1), &mut a, &mut b);
+/// assert_eq!((a, b), (2, 1));
+///```
+#[proc_macro]
+pub fn ct_swap(input: TokenStream) -> TokenStream {
+  let SelectInput { choice, a, b } = parse_macro_input!(input);
+
+  let lints = allowed_lints();
+  let output = quote! {
+    match (#choice, #a, #b) {
+      (choice, a, b) => {
+        /* Name the bound here so that a type without it produces a clear error. */
+        #[inline(always)]
+        #lints
+        fn ct_swap<T: ::subtle::ConditionallySelectable>(
+          choice: ::subtle::Choice,
+          a: &mut T,
+          b: &mut T,
+        ) {
+          T::conditional_swap(a, b, choice);
+        }
+        ct_swap(choice, a, b)
+      }
+    }
+  };
+
+  output.into()
+}
+
+struct LookupInput {
+  index: Expr,
+  entries: Vec<Expr>,
+}
+
+impl Parse for LookupInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let index = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let entries = match input.parse()? {
+      Expr::Array(ExprArray { elems, .. }) if !elems.is_empty() => elems.into_iter().collect(),
+      table => {
+        return Err(Code::InvalidMacroInput.error(
+          table,
+          "expected a non-empty list of entries such as `[a, b, c]`",
+        ))
+      },
+    };
+    if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+    }
+    Ok(Self { index, entries })
+  }
+}
+
+/// Select from a table of values listed inline, in constant time.
+///
+/// `ct_lookup!(index, [e0, e1, e2])` evaluates every entry and conditionally assigns the one at
+/// position `index` (a `usize`) with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html),
+/// like the `ct_lookup()` method generated by [`CtLookup`](derive@CtLookup). The entries' type must
+/// also implement [`Default`], which is returned if `index` is out of bounds.
+///
+///```
+/// use subtle_derive::ct_lookup;
+///
+/// let secret_index = 2;
+/// let x: u32 = ct_lookup!(secret_index, [10, 20, 30, 40]);
+/// assert_eq!(x, 30);
+/// let y: u32 = ct_lookup!(secret_index + 5, [10, 20, 30, 40]);
+/// assert_eq!(y, 0);
+///```
+#[proc_macro]
+pub fn ct_lookup(input: TokenStream) -> TokenStream {
+  let LookupInput { index, entries } = parse_macro_input!(input);
+
+  /* Use hygienic names for locals, so they can't shadow anything in the entries. */
+  let index_var = Ident::new("index", Span::mixed_site());
+  let ret = Ident::new("ret", Span::mixed_site());
+  let assign_stmts = entries.iter().enumerate().map(|(i, entry)| {
+    quote! {
+      ::subtle::ConditionallySelectable::conditional_assign(
+        &mut #ret,
+        &(#entry),
+        ::subtle::ConstantTimeEq::ct_eq(&#index_var, &#i),
+      );
+    }
+  });
+
+  let output = quote! {
+    {
+      let #index_var: usize = #index;
+      let mut #ret = ::core::default::Default::default();
+      #(#assign_stmts)*
+      #ret
+    }
+  };
+
+  output.into()
+}
+
+struct AssertInput {
+  a: Expr,
+  b: Expr,
+  message: Option<proc_macro2::TokenStream>,
+}
+
+impl Parse for AssertInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let a = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let b = input.parse()?;
+    let message = if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+      Some(input.parse()?).filter(|message: &proc_macro2::TokenStream| !message.is_empty())
+    } else {
+      None
+    };
+    Ok(Self { a, b, message })
+  }
+}
+
+/* Generate an assertion on the result of ct_eq() which never formats the operands themselves. */
+fn ct_assert(input: AssertInput, expect_eq: bool) -> proc_macro2::TokenStream {
+  let AssertInput { a, b, message } = input;
+  let (op, negate) = if expect_eq {
+    ("ct_eq", quote! { ! })
+  } else {
+    ("ct_ne", quote! {})
+  };
+  let description = format!(
+    "assertion failed: `{}({}, {})` (values redacted)",
+    op,
+    quote!(#a),
+    quote!(#b),
+  );
+  let panic_stmt = match message {
+    Some(message) => quote! {
+      ::core::panic!("{}: {}", #description, ::core::format_args!(#message))
+    },
+    None => quote! { ::core::panic!("{}", #description) },
+  };
+
+  quote! {
+    match (&(#a), &(#b)) {
+      (a, b) => {
+        if #negate <bool as ::core::convert::From<::subtle::Choice>>::from(
+          ::subtle::ConstantTimeEq::ct_eq(a, b),
+        ) {
+          #panic_stmt
+        }
+      }
+    }
+  }
+}
+
+/// Assert that two values are equal according to
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html).
+///
+/// Unlike [`assert_eq!`], the operands are never passed to [`Debug`](core::fmt::Debug) or
+/// [`PartialEq`], so a failing test can't print secret values. The panic message only contains the
+/// source text of the operands, followed by an optional custom message in the same format as
+/// [`assert_eq!`].
+///
+///```
+/// use subtle_derive::ct_assert_eq;
+///
+/// let key = [1u8, 2, 3, 4];
+/// ct_assert_eq!(key, [1u8, 2, 3, 4]);
+/// ct_assert_eq!(key, [1u8, 2, 3, 4], "key {} should match", 0);
+///```
+///
+///```should_panic
+/// use subtle_derive::ct_assert_eq;
+///
+/// ct_assert_eq!([1u8, 2], [1u8, 3]);
+///```
+#[proc_macro]
+pub fn ct_assert_eq(input: TokenStream) -> TokenStream {
+  ct_assert(parse_macro_input!(input), true).into()
+}
+
+/// Assert that two values are not equal according to
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html).
+///
+/// This is the counterpart to [`ct_assert_eq!`], and likewise never formats the operands.
+///
+///```
+/// use subtle_derive::ct_assert_ne;
+///
+/// ct_assert_ne!([1u8, 2], [1u8, 3]);
+/// ct_assert_ne!(5u32, 6u32, "values should differ");
+///```
+///
+///```should_panic
+/// use subtle_derive::ct_assert_ne;
+///
+/// ct_assert_ne!(5u32, 5u32);
+///```
+#[proc_macro]
+pub fn ct_assert_ne(input: TokenStream) -> TokenStream {
+  ct_assert(parse_macro_input!(input), false).into()
+}
+
+struct FuzzTargetInput {
+  ty: Type,
+  /* A function or closure taking two references to `ty`, which returns whether they're equal. */
+  reference: Option<Expr>,
+}
+
+impl Parse for FuzzTargetInput {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let ty = input.parse()?;
+    let mut reference = None;
+    if input.peek(Token![,]) {
+      input.parse::<Token![,]>()?;
+      if !input.is_empty() {
+        reference = Some(input.parse()?);
+        if input.peek(Token![,]) {
+          input.parse::<Token![,]>()?;
+        }
+      }
+    }
+    Ok(Self { ty, reference })
+  }
+}
+
+/// Generate a [`cargo-fuzz`](https://rust-fuzz.github.io/book/cargo-fuzz.html) target checking a
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html)
+/// impl against a reference comparison.
+///
+/// `ct_fuzz_target!(T)` expands to a `libfuzzer_sys::fuzz_target!` which decodes two values of `T`
+/// from the fuzzer's input with
+/// [`arbitrary::Arbitrary`](https://docs.rs/arbitrary/latest/arbitrary/trait.Arbitrary.html),
+/// and asserts that `ct_eq()` agrees with `==`, that `ct_ne()` agrees with `!=`, and that each value
+/// is equal to itself. This differentially fuzzes the code generated by the derives, including the
+/// byte-wise and chunked comparisons. If the [`PartialEq`] impl of `T` is itself generated from
+/// `ct_eq()`, as by [`ConstEq`](derive@ConstEq), a reference comparison should be given instead,
+/// as a function or closure taking two `&T` and returning a `bool`, as in
+/// `ct_fuzz_target!(T, |a: &T, b: &T| a.id == b.id && a.key == b.key)`.
+///
+/// The target must be expanded at the root of a fuzz target crate with `#![no_main]`, which has
+/// `libfuzzer-sys` as a dependency, and `T` must implement `arbitrary::Arbitrary` and
+/// [`Debug`](core::fmt::Debug).
+///
+///```ignore
+/// #![no_main]
+/// use my_crate::Key;
+/// use subtle_derive::ct_fuzz_target;
+///
+/// ct_fuzz_target!(Key, |a: &Key, b: &Key| a.bytes == b.bytes);
+///```
+#[proc_macro]
+pub fn ct_fuzz_target(input: TokenStream) -> TokenStream {
+  let FuzzTargetInput { ty, reference } = parse_macro_input!(input);
+  let a = Ident::new("a", Span::mixed_site());
+  let b = Ident::new("b", Span::mixed_site());
+  let expected = match reference {
+    Some(reference) => quote!((#reference)(&#a, &#b)),
+    None => quote!(#a == #b),
+  };
+
+  let output = quote! {
+    ::libfuzzer_sys::fuzz_target!(|input: (#ty, #ty)| {
+      let (#a, #b) = input;
+      let expected: bool = #expected;
+      ::core::assert_eq!(
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(&#a, &#b)),
+        expected,
+        "ct_eq() disagrees with the reference comparison"
+      );
+      ::core::assert_eq!(
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_ne(&#a, &#b)),
+        !expected,
+        "ct_ne() disagrees with the reference comparison"
+      );
+      ::core::assert!(
+        ::core::convert::Into::<bool>::into(::subtle::ConstantTimeEq::ct_eq(&#a, &#a)),
+        "ct_eq() is not reflexive"
+      );
+    });
+  };
+
+  output.into()
+}
+
+/* A single constant-time predicate within ct_validate!(). */
+enum Predicate {
+  /* `value in range` */
+  InRange(Expr, ExprRange),
+  /* Any other expression, which may be a comparison between two values. */
+  Expr(Expr),
+}
+
+impl Parse for Predicate {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let expr = input.parse()?;
+    if input.peek(Token![in]) {
+      input.parse::<Token![in]>()?;
+      match input.parse()? {
+        Expr::Range(range) => Ok(Self::InRange(expr, range)),
+        other => Err(Code::InvalidMacroInput.error(other, "expected a range such as `1..=10`")),
+      }
+    } else {
+      Ok(Self::Expr(expr))
+    }
+  }
+}
+
+/* Generate an expression evaluating to a Choice for a single predicate. */
+fn predicate_choice(predicate: Predicate) -> proc_macro2::TokenStream {
+  match predicate {
+    Predicate::InRange(value, range) => range_check(&parse_quote! { &(#value) }, &range),
+    Predicate::Expr(Expr::Binary(ExprBinary {
+      left, op, right, ..
+    }))
+      if matches!(
+        op,
+        BinOp::Eq(_) | BinOp::Ne(_) | BinOp::Lt(_) | BinOp::Gt(_) | BinOp::Le(_) | BinOp::Ge(_)
+      ) =>
+    {
+      let (negate, method) = match op {
+        BinOp::Eq(_) => (false, quote! { ConstantTimeEq::ct_eq }),
+        BinOp::Ne(_) => (true, quote! { ConstantTimeEq::ct_eq }),
+        BinOp::Lt(_) => (false, quote! { ConstantTimeLess::ct_lt }),
+        BinOp::Gt(_) => (false, quote! { ConstantTimeGreater::ct_gt }),
+        BinOp::Le(_) => (true, quote! { ConstantTimeGreater::ct_gt }),
+        _ => (true, quote! { ConstantTimeLess::ct_lt }),
+      };
+      let negate = if negate {
+        quote! { ! }
+      } else {
+        quote! {}
+      };
+      quote! { #negate ::subtle::#method(&(#left), &(#right)) }
+    },
+    Predicate::Expr(choice) => quote! { ::core::convert::Into::<::subtle::Choice>::into(#choice) },
+  }
+}
+
+/// Combine a series of constant-time checks into a single
+/// [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html).
+///
+/// Each comma-separated predicate is evaluated to a `Choice`, and the results are combined with
+/// `&`, so every predicate is evaluated regardless of the others (there is no short-circuiting). The
+/// result can then be branched on once it is safe to do so, e.g. as the validity of a
+/// [`subtle::CtOption`](https://docs.rs/subtle/latest/subtle/struct.CtOption.html). Predicates may
+/// be:
+/// - `x in lo..hi` (or any other range): a range check with `ct_lt()`/`ct_gt()`, as for
+///   `#[ct(range = "...")]` in [`CtNew`](derive@CtNew).
+/// - `a == b`, `a != b`: comparisons with `ct_eq()`.
+/// - `a < b`, `a > b`, `a <= b`, `a >= b`: comparisons with `ct_lt()` and `ct_gt()`.
+/// - Any other expression evaluating to a `Choice`.
+///
+///```
+/// use subtle::Choice;
+/// use subtle_derive::ct_validate;
+///
+/// let (version, len, tag, expected_tag) = (2u8, 32u32, [1u8; 4], [1u8; 4]);
+/// let is_valid = ct_validate!(
+///   version in 1..=2,
+///   len <= 64,
+///   tag == expected_tag,
+///   Choice::from((len % 2 == 0) as u8),
+/// );
+/// assert!(bool::from(is_valid));
+/// assert!(bool::from(!ct_validate!(version in 3.., len <= 64)));
+///```
+#[proc_macro]
+pub fn ct_validate(input: TokenStream) -> TokenStream {
+  let predicates =
+    parse_macro_input!(input with punctuated::Punctuated::<Predicate, Token![,]>::parse_terminated);
+
+  let choices = predicates.into_iter().map(predicate_choice);
+
+  let output = quote! {
+    <::subtle::Choice as ::core::convert::From<u8>>::from(1u8) #(& (#choices))*
+  };
+
+  output.into()
+}
+
+/// Rewrite `if`/`else` expressions on secret conditions in a function into constant-time
+/// selections.
+///
+/// Any `if cond { a } else { b }` whose condition is syntactically known to be a
+/// [`subtle::Choice`](https://docs.rs/subtle/latest/subtle/struct.Choice.html) is rewritten into
+/// `conditional_select(&b, &a, cond)`, so both arms are evaluated and the result is selected
+/// without a branch. Both arms must then evaluate to a type implementing
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html),
+/// and may not `return`, `break`, `continue`, or use `?`. A condition is known to be a `Choice` if
+/// it is:
+/// - a call to `ct_eq()`, `ct_ne()`, `ct_gt()`, `ct_lt()`, or `Choice::from()`,
+/// - an invocation of [`ct_validate!`] or [`ct_eq_chain!`],
+/// - an argument of type `Choice`, or a variable bound by `let` to any of the above,
+/// - or a combination of the above with `!`, `&`, `|`, or `^`.
+///
+/// Arguments may also be marked `#[secret]`. Variables computed from secret arguments are secret
+/// too, and any other `if` or `match` on a secret value is rejected at compile time, since it
+/// would branch on secret data. A `let` binding a public value, such as the length of a secret,
+/// shadows a secret variable of the same name until the end of its block.
+///
+///```
+/// use subtle::{ConstantTimeEq, ConstantTimeGreater};
+/// use subtle_derive::ct_branchless;
+///
+/// #[ct_branchless]
+/// fn fee(#[secret] tier: u8, #[secret] amount: u64) -> u64 {
+///   let is_premium = tier.ct_eq(&2);
+///   if is_premium {
+///     0
+///   } else if amount.ct_gt(&1000) {
+///     10
+///   } else {
+///     20
+///   }
+/// }
+///
+/// assert_eq!(fee(2, 5000), 0);
+/// assert_eq!(fee(1, 5000), 10);
+/// assert_eq!(fee(1, 5), 20);
+///
+/// #[ct_branchless]
+/// fn padding(#[secret] message: &[u8]) -> usize {
+///   let message = message.len();
+///   if message % 16 == 0 { 16 } else { 16 - message % 16 }
+/// }
+///
+/// assert_eq!(padding(&[0; 20]), 12);
+///```
+///
+/// Branching on a secret value is an error:
+///
+///```compile_fail
+/// use subtle_derive::ct_branchless;
+///
+/// #[ct_branchless]
+/// fn leak(#[secret] key: u8) -> u8 {
+///   match key {
+///     0 => 1,
+///     _ => 2,
+///   }
+/// }
+///```
+#[proc_macro_attribute]
+pub fn ct_branchless(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let mut item: ItemFn = parse_macro_input!(item);
+  let errors = branchless::rewrite_fn(&mut item);
+
+  let output = quote! {
+    #item
+    #errors
+  };
+
+  output.into()
+}
+
+/// Check that the methods of a hand-written impl block only operate on their inputs in constant
+/// time.
+///
+/// Every method argument of the impl, including `self`, is considered secret, along with any
+/// variable computed from them. A compile error is produced for any of the following operations on
+/// secret values, since they typically compile to a branch or a secret-dependent memory access:
+/// - comparisons with `==`, `!=`, `<`, `<=`, `>`, or `>=`,
+/// - short-circuiting `&&` or `||`,
+/// - `if`, `while`, or `match` on a secret condition,
+/// - indexing with a secret index.
+///
+/// Arguments which are public, such as lengths or protocol versions, can be excluded by marking
+/// the method with [`macro@ct_public_inputs`]. Otherwise, the impl block itself is emitted
+/// unchanged, so this only adds a build-time check. As with
+/// [`macro@ct_branchless`], the analysis is purely syntactic, and operations hidden behind function
+/// calls are not checked.
+///
+///```
+/// use subtle::{Choice, ConstantTimeEq};
+/// use subtle_derive::constant_time_impl;
+///
+/// struct Key(u64, u32);
+///
+/// #[constant_time_impl]
+/// impl ConstantTimeEq for Key {
+///   fn ct_eq(&self, other: &Self) -> Choice {
+///     let tag = self.1.ct_eq(&other.1);
+///     self.0.ct_eq(&other.0) & tag
+///   }
+/// }
+///
+/// assert_eq!(1, Key(1, 2).ct_eq(&Key(1, 2)).unwrap_u8());
+/// assert_eq!(0, Key(1, 2).ct_eq(&Key(1, 3)).unwrap_u8());
+///```
+///
+/// Comparing secret values with `==` is an error:
+///
+///```compile_fail
+/// use subtle::{Choice, ConstantTimeEq};
+/// use subtle_derive::constant_time_impl;
+///
+/// struct Key(u64);
+///
+/// #[constant_time_impl]
+/// impl ConstantTimeEq for Key {
+///   fn ct_eq(&self, other: &Self) -> Choice {
+///     Choice::from((self.0 == other.0) as u8)
+///   }
+/// }
+///```
+#[proc_macro_attribute]
+pub fn constant_time_impl(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let mut item: ItemImpl = parse_macro_input!(item);
+  let errors = checks::check_impl(&mut item);
+
+  let output = quote! {
+    #item
+    #errors
+  };
+
+  output.into()
+}
+
+/// Arguments to `#[ct_verify(...)]`.
+struct VerifyArgs {
+  /// Functions generating the arguments for each of dudect's two input classes.
+  left: Path,
+  right: Path,
+  /// The number of measurements to take on each run.
+  samples: LitInt,
+  /// The predicate which must hold for the measurement code to be compiled.
+  cfg: proc_macro2::TokenStream,
+}
+
+impl VerifyArgs {
+  /* Parse the options given to `attr`, reporting missing generators at `span`. */
+  fn from_metas(metas: impl IntoIterator<Item=NestedMeta>, span: Span, attr: &str) -> Result<Self> {
+    let (mut left, mut right) = (None, None);
+    let mut samples = LitInt::new("100_000", Span::call_site());
+    let mut cfg = quote!(ct_verify);
+    for meta in metas {
+      match meta {
+        NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+          ref path,
+          lit: Lit::Str(ref lit),
+          ..
+        }))
+          if path.is_ident("left") || path.is_ident("right") =>
+        {
+          let generator = Some(lit.parse::<Path>()?);
+          if path.is_ident("left") {
+            left = generator;
+          } else {
+            right = generator;
+          }
+        },
+        NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+          ref path,
+          lit: Lit::Int(ref lit),
+          ..
+        }))
+          if path.is_ident("samples") =>
+        {
+          samples = lit.clone();
+        },
+        NestedMeta::Meta(Meta::List(MetaList {
+          ref path,
+          ref nested,
+          ..
+        }))
+          if path.is_ident("cfg") && nested.len() == 1 =>
+        {
+          cfg = quote!(#nested);
+        },
+        meta => {
+          return Err(Code::InvalidMacroInput.error(
+            &meta,
+            "expected `left = \"...\"`, `right = \"...\"`, `samples = N`, or `cfg(...)`",
+          ))
+        },
+      }
+    }
+    match (left, right) {
+      (Some(left), Some(right)) => Ok(Self {
+        left,
+        right,
+        samples,
+        cfg,
+      }),
+      _ => Err(Code::InvalidMacroInput.error_at(
+        span,
+        format_args!(
+          "{} requires both `left = \"...\"` and `right = \"...\"`",
+          attr
+        ),
+      )),
+    }
+  }
+}
+
+impl Parse for VerifyArgs {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let metas = punctuated::Punctuated::<NestedMeta, Token![,]>::parse_terminated(input)?;
+    Self::from_metas(metas, input.span(), "#[ct_verify]")
   }
 }
 
-/// Derive macro for
-/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html)
-/// implemented using [`subtle::IteratedEq`](https://docs.rs/subtle/latest/subtle/struct.IteratedEq.html).
+/* Generate a function `bench_name` which can be passed to `dudect_bencher::ctbench_main!`, timing
+ * `run` for inputs drawn at random from the two classes of `args`. `run` refers to the generated
+ * inputs as `args`. */
+fn dudect_bench(
+  vis: &Visibility,
+  bench_name: &Ident,
+  doc: String,
+  args: VerifyArgs,
+  run: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+  let VerifyArgs {
+    left,
+    right,
+    samples,
+    cfg,
+  } = args;
+  quote! {
+    #[cfg(#cfg)]
+    #[doc = #doc]
+    #vis fn #bench_name(
+      runner: &mut ::dudect_bencher::CtRunner,
+      rng: &mut ::dudect_bencher::BenchRng,
+    ) {
+      let mut inputs = ::std::vec::Vec::new();
+      for _ in 0..#samples {
+        if ::dudect_bencher::rand::Rng::gen::<bool>(rng) {
+          inputs.push((::dudect_bencher::Class::Left, #left(rng)));
+        } else {
+          inputs.push((::dudect_bencher::Class::Right, #right(rng)));
+        }
+      }
+      for (class, args) in inputs {
+        runner.run_one(class, || #run);
+      }
+    }
+  }
+}
+
+/// Generate a [dudect](https://docs.rs/dudect-bencher/latest/dudect_bencher/) timing-leak test
+/// for a function.
+///
+/// Alongside the annotated function `f`, this generates a benchmark function `f_ct_verify`, which
+/// can be passed to `dudect_bencher::ctbench_main!`. Each run draws
+/// `samples` inputs (100,000 by default) at random from one of two classes, generated by the
+/// functions named by `left` and `right`. Each generator has the signature
+/// `fn(&mut dudect_bencher::BenchRng) -> (A, B, ...)`, returning one owned value for each argument
+/// of `f`. Arguments taken by reference are passed as `&a`, and any others are cloned for each
+/// call. Typically one class uses a fixed input, and the other uses random inputs, so that any
+/// difference in timing between the classes indicates that `f` leaks information about its input.
+///
+/// The benchmark function is only compiled when `cfg(ct_verify)` is set, as with
+/// `RUSTFLAGS="--cfg ct_verify" cargo bench`, so that `dudect-bencher` may be an optional
+/// dependency. A different predicate can be provided with e.g. `cfg(feature = "dudect")`.
+/// As cargo doesn't know about `cfg(ct_verify)`, it should be declared in the manifest of the crate
+/// using it, as `check-cfg = ['cfg(ct_verify)']` under `unexpected_cfgs` in `[lints.rust]`, to
+/// avoid warnings from that lint.
 ///
 ///```
-/// use subtle::ConstantTimeEq;
-/// use subtle_derive::ConstantTimeEq;
+/// use subtle::{Choice, ConstantTimeEq};
+/// use subtle_derive::ct_verify;
 ///
-/// #[derive(ConstantTimeEq)]
-/// struct S { x: u8, y: u8 }
-/// let s1 = S { x: 0, y: 1 };
-/// let s2 = S { x: 0, y: 2 };
-/// assert!(bool::from(s1.ct_eq(&s1)));
-/// assert!(bool::from(s2.ct_eq(&s2)));
-/// assert!(bool::from(!s1.ct_eq(&s2)));
+/// #[ct_verify(left = "equal_keys", right = "random_keys", cfg(test))]
+/// fn keys_match(a: &[u8; 32], b: &[u8; 32]) -> Choice {
+///   a.ct_eq(b)
+/// }
 ///
-/// #[derive(ConstantTimeEq)]
-/// struct T(u8, u8);
-/// let t1 = T(0, 1);
-/// let t2 = T(0, 2);
-/// assert!(bool::from(t1.ct_eq(&t1)));
-/// assert!(bool::from(t2.ct_eq(&t2)));
-/// assert!(bool::from(!t1.ct_eq(&t2)));
+/// assert_eq!(1, keys_match(&[1; 32], &[1; 32]).unwrap_u8());
+/// assert_eq!(0, keys_match(&[1; 32], &[2; 32]).unwrap_u8());
 ///```
-#[proc_macro_derive(ConstantTimeEq)]
-pub fn derive_eq(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, data, .. } = parse_macro_input!(input);
+///
+/// The generators and the `ctbench_main!` invocation then live alongside the benchmark:
+///
+///```ignore
+/// use dudect_bencher::{ctbench_main, rand::RngCore, BenchRng};
+///
+/// fn equal_keys(_rng: &mut BenchRng) -> ([u8; 32], [u8; 32]) {
+///   ([0; 32], [0; 32])
+/// }
+///
+/// fn random_keys(rng: &mut BenchRng) -> ([u8; 32], [u8; 32]) {
+///   let mut b = [0; 32];
+///   rng.fill_bytes(&mut b);
+///   ([0; 32], b)
+/// }
+///
+/// ctbench_main!(keys_match_ct_verify);
+///```
+#[proc_macro_attribute]
+pub fn ct_verify(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let verify_args: VerifyArgs = parse_macro_input!(attr);
+  let item: ItemFn = parse_macro_input!(item);
+  let ItemFn { vis, sig, .. } = &item;
+  let name = &sig.ident;
+  let bench_name = format_ident!("{}_ct_verify", name, span = name.span());
 
-  /* Generate the function body of a ct_eq() implementation. */
-  let eq_block =
-    {
-      let field_names = field_names(data);
-      let mut eq_stmts: Vec<Stmt> = vec![
-        parse_str("use ::subtle::IteratedOperation;").unwrap(),
-        parse_str("let mut ret = ::subtle::IteratedEq::initiate();").unwrap(),
-      ];
-      eq_stmts.extend(field_names.into_iter().map(|name| {
-        parse_str(&format!("ret.apply_eq(&self.{}, &other.{});", name, name)).unwrap()
-      }));
-      eq_stmts.push(parse_str("return ret.extract_result();").unwrap());
-      Block {
-        brace_token: token::Brace {
-          span: Span::mixed_site(),
-        },
-        stmts: eq_stmts,
-      }
-    };
+  /* Pass each generated argument by reference or by value, matching the function's signature. */
+  let mut args: Vec<proc_macro2::TokenStream> = Vec::new();
+  for (i, input) in sig.inputs.iter().enumerate() {
+    let index = Index::from(i);
+    match input {
+      FnArg::Receiver(receiver) => {
+        return Code::UnsupportedItem
+          .error(receiver, "#[ct_verify] does not support methods")
+          .to_compile_error()
+          .into();
+      },
+      FnArg::Typed(PatType { ty, .. }) => match &**ty {
+        Type::Reference(_) => args.push(quote!(&args.#index)),
+        _ => args.push(quote!(::core::clone::Clone::clone(&args.#index))),
+      },
+    }
+  }
 
-  /* Insert the ct_eq() block into the quoted trait method. */
+  let doc = format!(
+    " Run a `dudect` benchmark of [`{}`], comparing its timing across the two classes of inputs.",
+    name
+  );
+  let bench = dudect_bench(
+    vis,
+    &bench_name,
+    doc,
+    verify_args,
+    quote!(#name(#(#args),*)),
+  );
   let output = quote! {
-    impl ::subtle::ConstantTimeEq for #ident {
-      #[inline]
-      fn ct_eq(&self, other: &Self) -> ::subtle::Choice {
-        #eq_block
-      }
-    }
+    #item
+
+    #bench
   };
 
   output.into()
 }
 
-/// Implement [`PartialEq`] and [`Eq`] given a [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html) implementation.
+/// Generate a [dudect](https://docs.rs/dudect-bencher/latest/dudect_bencher/) timing-leak test
+/// for the derived `ct_eq()` of a type.
+///
+/// This generates a benchmark function named after the type, as `<type>_ct_verify`, which can be
+/// passed to `dudect_bencher::ctbench_main!`. It takes the same options as
+/// [`macro@ct_verify`], given with `#[ct(verify(...))]`, except that the `left` and `right`
+/// generators each return a pair of values to compare with `ct_eq()`. Typically one class compares
+/// a fixed value against itself, and the other compares it against random values, so that any
+/// difference in timing between the classes indicates that `ct_eq()` leaks where the values differ.
+///
+/// As with [`macro@ct_verify`], the benchmark is only compiled when `cfg(ct_verify)` is set, unless
+/// a different predicate is given with `cfg(...)`. Like [`CtTest`](derive@CtTest), it is not
+/// emitted within an anonymous scope, so that it can be named. Generic types are not supported.
 ///
 ///```
-/// use subtle_derive::{ConstantTimeEq, ConstEq};
+/// use subtle_derive::{ConstantTimeEq, CtVerify};
 ///
-/// #[derive(Debug, ConstantTimeEq, ConstEq)]
-/// pub struct S(pub u8);
+/// #[derive(ConstantTimeEq, CtVerify)]
+/// #[ct(verify(left = "equal_keys", right = "random_keys", samples = 10_000))]
+/// pub struct Key { id: u32, bytes: [u8; 32] }
+///```
 ///
-/// assert!(S(0) == S(0));
-/// assert!(S(0) != S(1));
+/// The generators and the `ctbench_main!` invocation then live alongside the benchmark:
+///
+///```ignore
+/// use dudect_bencher::{ctbench_main, rand::RngCore, BenchRng};
+///
+/// fn equal_keys(_rng: &mut BenchRng) -> (Key, Key) {
+///   (Key { id: 0, bytes: [0; 32] }, Key { id: 0, bytes: [0; 32] })
+/// }
+///
+/// fn random_keys(rng: &mut BenchRng) -> (Key, Key) {
+///   let mut bytes = [0; 32];
+///   rng.fill_bytes(&mut bytes);
+///   (Key { id: 0, bytes: [0; 32] }, Key { id: 0, bytes })
+/// }
+///
+/// ctbench_main!(key_ct_verify);
 ///```
-#[proc_macro_derive(ConstEq)]
-pub fn derive_eq_impls(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, .. } = parse_macro_input!(input);
+#[proc_macro_derive(CtVerify, attributes(ct))]
+pub fn derive_verify(input: TokenStream) -> TokenStream {
+  let DeriveInput {
+    ident,
+    vis,
+    generics,
+    attrs,
+    ..
+  } = parse_macro_input!(input);
+  if !generics.params.is_empty() {
+    Code::GenericType.panic("CtVerify can't be derived for generic types");
+  }
+  let ContainerAttrs { krate, verify, .. } = container_attrs(&attrs);
+  let verify_args = match VerifyArgs::from_metas(
+    verify.unwrap_or_default(),
+    ident.span(),
+    "#[ct(verify(...))]",
+  ) {
+    Ok(verify_args) => verify_args,
+    Err(err) => return err.to_compile_error().into(),
+  };
+  let bench_name = format_ident!(
+    "{}_ct_verify",
+    ident.unraw().to_string().to_lowercase(),
+    span = ident.span()
+  );
 
-  let output = quote! {
-    impl PartialEq for #ident {
-      fn eq(&self, other: &Self) -> bool {
-        use ::subtle::ConstantTimeEq;
-        self.ct_eq(other).into()
-      }
-    }
+  let doc = format!(
+    " Run a `dudect` benchmark of the `ct_eq()` impl for [`{}`], comparing its timing across the \
+     two classes of inputs.",
+    ident.unraw()
+  );
+  let output = dudect_bench(&vis, &bench_name, doc, verify_args, quote! {
+    <#ident as ::subtle::ConstantTimeEq>::ct_eq(&args.0, &args.1)
+  });
+
+  /* As with CtTest, the benchmark must stay outside any anonymous scope to be named, so this only
+   * rewrites its paths. */
+  paths::rewrite_subtle_paths(output, &paths::subtle_path(krate.as_ref())).into()
+}
+
+/// Reject early exits from a function which depend on its secret arguments.
+///
+/// Arguments marked `#[secret]` are considered secret, along with any variable computed from them,
+/// as with [`macro@ct_branchless`]. Unlike [`macro@ct_branchless`], the function is not rewritten,
+/// and a compile error is produced for:
+/// - `return`, `break`, `continue`, or `?` within an `if` or `match` on a secret value,
+/// - `?` applied to a secret value,
+/// - indexing with a secret index.
+///
+/// Branches on secret values which don't exit early are still permitted, since both arms may take
+/// the same time. Use [`macro@constant_time_impl`] for a stricter check.
+///
+///```
+/// use subtle::{Choice, ConstantTimeEq};
+/// use subtle_derive::deny_secret_branches;
+///
+/// #[deny_secret_branches]
+/// fn all_zero(#[secret] bytes: &[u8]) -> Choice {
+///   let mut ret = Choice::from(1);
+///   for b in bytes {
+///     ret &= b.ct_eq(&0);
+///   }
+///   ret
+/// }
+///
+/// assert_eq!(1, all_zero(&[0, 0]).unwrap_u8());
+/// assert_eq!(0, all_zero(&[0, 1]).unwrap_u8());
+///```
+///
+/// Returning early on a secret value is an error:
+///
+///```compile_fail
+/// use subtle_derive::deny_secret_branches;
+///
+/// #[deny_secret_branches]
+/// fn all_zero(#[secret] bytes: &[u8]) -> bool {
+///   for b in bytes {
+///     if *b != 0 {
+///       return false;
+///     }
+///   }
+///   true
+/// }
+///```
+#[proc_macro_attribute]
+pub fn deny_secret_branches(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let mut item: ItemFn = parse_macro_input!(item);
+  let errors = checks::check_fn(checks::Rules::Branches, &mut item);
 
-    impl Eq for #ident {}
+  let output = quote! {
+    #item
+    #errors
   };
 
   output.into()
 }
 
-/// Derive macro for
-/// [`subtle::ConstantTimeGreater`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html)
-/// implemented using
-/// [`subtle::IteratedGreater`](https://docs.rs/subtle/latest/subtle/struct.IteratedGreater.html).
+/// Reject loops in a function whose number of iterations depends on its secret arguments.
+///
+/// Arguments marked `#[secret]` are considered secret, along with any variable computed from them,
+/// as with [`macro@ct_branchless`]. Lengths are considered public, so iterating over a secret
+/// slice or up to its `len()` is permitted. A compile error is produced for:
+/// - a `while` loop on a secret condition,
+/// - a `for` loop over a range with secret bounds,
+/// - `break`, `continue`, or `return` within an `if` or `match` on a secret value inside a loop.
+///
+/// This catches the classic bug of comparing two values only until the first difference:
 ///
+///```compile_fail
+/// use subtle_derive::ct_loop_bound;
+///
+/// #[ct_loop_bound]
+/// fn common_prefix(#[secret] a: &[u8], #[secret] b: &[u8]) -> usize {
+///   let mut i = 0;
+///   while i < a.len() && i < b.len() && a[i] == b[i] {
+///     i += 1;
+///   }
+///   i
+/// }
 ///```
-/// use subtle::ConstantTimeGreater;
-/// use subtle_derive::ConstantTimeGreater;
 ///
-/// #[derive(ConstantTimeGreater)]
-/// struct S { x: u8, y: u8 }
-/// let s1 = S { x: 0, y: 1 };
-/// let s2 = S { x: 0, y: 2 };
-/// assert!(bool::from(!s1.ct_gt(&s1)));
-/// assert!(bool::from(s2.ct_gt(&s1)));
+/// Instead, always iterate over the whole input:
 ///
-/// #[derive(ConstantTimeGreater)]
-/// struct T(u8, u8);
-/// let t1 = T(0, 1);
-/// let t2 = T(0, 2);
-/// assert!(bool::from(!t1.ct_gt(&t1)));
-/// assert!(bool::from(t2.ct_gt(&t1)));
 ///```
-#[proc_macro_derive(ConstantTimeGreater)]
-pub fn derive_gt(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, data, .. } = parse_macro_input!(input);
-
-  /* Generate the function body of a ct_gt() implementation. */
-  let gt_block = {
-    let field_names = field_names(data);
-    let mut gt_stmts: Vec<Stmt> = vec![
-      parse_str("use ::subtle::IteratedOperation;").unwrap(),
-      parse_str("let mut ret = ::subtle::IteratedGreater::initiate();").unwrap(),
-    ];
-    for name in field_names.into_iter() {
-      gt_stmts.push(parse_str(&format!("ret.apply_gt(&self.{}, &other.{});", name, name)).unwrap());
-    }
-    gt_stmts.push(parse_str("return ret.extract_result();").unwrap());
-    Block {
-      brace_token: token::Brace {
-        span: Span::mixed_site(),
-      },
-      stmts: gt_stmts,
-    }
-  };
+/// use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+/// use subtle_derive::ct_loop_bound;
+///
+/// #[ct_loop_bound]
+/// fn common_prefix(#[secret] a: &[u8], #[secret] b: &[u8]) -> usize {
+///   let mut matching = Choice::from(1);
+///   let mut ret = 0u64;
+///   for i in 0..a.len().min(b.len()) {
+///     matching &= a[i].ct_eq(&b[i]);
+///     ret.conditional_assign(&(i as u64 + 1), matching);
+///   }
+///   ret as usize
+/// }
+///
+/// assert_eq!(2, common_prefix(&[1, 2, 3], &[1, 2, 4]));
+/// assert_eq!(0, common_prefix(&[1, 2, 3], &[2, 2, 3]));
+///```
+#[proc_macro_attribute]
+pub fn ct_loop_bound(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let mut item: ItemFn = parse_macro_input!(item);
+  let errors = checks::check_fn(checks::Rules::Loops, &mut item);
 
-  /* Insert the ct_gt() block into the quoted trait method. */
   let output = quote! {
-    impl ::subtle::ConstantTimeGreater for #ident {
-      #[inline]
-      fn ct_gt(&self, other: &Self) -> ::subtle::Choice {
-        use ::subtle::ConstantTimeGreater;
-        #gt_block
-      }
-    }
+    #item
+    #errors
   };
 
   output.into()
 }
 
-/// Derive macro for [`subtle::ConstantTimeLess`] implemented using
-/// [`subtle::IteratedLess`](https://docs.rs/subtle/latest/subtle/struct.IteratedLess.html).
+/// Declare which arguments of a function are public, so that every other argument is secret.
 ///
-/// Note that [`subtle::ConstantTimeLess`] requires [`subtle::ConstantTimeGreater`] to be
-/// implemented as well, so this macro requires [`ConstantTimeGreater`] to also be derived as well.
+/// This is an alternative to marking each secret argument with `#[secret]`, for functions where
+/// most arguments are secret. It may be placed on a function along with [`macro@ct_branchless`],
+/// [`macro@deny_secret_branches`], [`macro@ct_loop_bound`], or [`macro@ct_equalize`], or on a
+/// method within an impl block
+/// marked [`macro@constant_time_impl`] (where `self` may also be listed). Branching on the listed
+/// arguments is then permitted, while branching on any other argument is rejected.
 ///
-/// [`subtle::ConstantTimeGreater`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeGreater.html
-/// [`subtle::ConstantTimeLess`]: https://docs.rs/subtle/latest/subtle/trait.ConstantTimeLess.html
+///```
+/// use subtle::{Choice, ConstantTimeEq};
+/// use subtle_derive::{ct_public_inputs, deny_secret_branches};
+///
+/// #[ct_public_inputs(version)]
+/// #[deny_secret_branches]
+/// fn check_tag(version: u8, tag: &[u8; 4], expected: &[u8; 4]) -> Option<Choice> {
+///   if version != 1 {
+///     return None;
+///   }
+///   Some(tag.ct_eq(expected))
+/// }
 ///
+/// assert!(check_tag(2, &[1; 4], &[1; 4]).is_none());
+/// assert_eq!(1, check_tag(1, &[1; 4], &[1; 4]).unwrap().unwrap_u8());
 ///```
-/// use subtle::ConstantTimeLess;
-/// use subtle_derive::{ConstantTimeGreater, ConstantTimeLess};
 ///
-/// #[derive(ConstantTimeGreater, ConstantTimeLess)]
-/// struct S { x: u8, y: u8 }
-/// let s1 = S { x: 0, y: 2 };
-/// let s2 = S { x: 0, y: 1 };
-/// assert!(bool::from(!s1.ct_lt(&s1)));
-/// assert!(bool::from(s2.ct_lt(&s1)));
+/// Any argument not listed is secret:
 ///
-/// #[derive(ConstantTimeGreater, ConstantTimeLess)]
-/// struct T(u8, u8);
-/// let t1 = T(0, 2);
-/// let t2 = T(0, 1);
-/// assert!(bool::from(!t1.ct_lt(&t1)));
-/// assert!(bool::from(t2.ct_lt(&t1)));
+///```compile_fail
+/// use subtle_derive::{ct_public_inputs, deny_secret_branches};
+///
+/// #[ct_public_inputs(version)]
+/// #[deny_secret_branches]
+/// fn check_tag(version: u8, tag: &[u8; 4], expected: &[u8; 4]) -> Option<bool> {
+///   if version != 1 || tag != expected {
+///     return None;
+///   }
+///   Some(true)
+/// }
 ///```
-#[proc_macro_derive(ConstantTimeLess)]
-pub fn derive_lt(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, data, .. } = parse_macro_input!(input);
-
-  /* Generate the function body of a ct_lt() implementation. */
-  let lt_block = {
-    let field_names = field_names(data);
-    let mut lt_stmts: Vec<Stmt> = vec![
-      parse_str("use ::subtle::IteratedOperation;").unwrap(),
-      parse_str("let mut ret = ::subtle::IteratedLess::initiate();").unwrap(),
-    ];
-    for name in field_names.into_iter() {
-      lt_stmts.push(parse_str(&format!("ret.apply_lt(&self.{}, &other.{});", name, name)).unwrap());
-    }
-    lt_stmts.push(parse_str("return ret.extract_result();").unwrap());
-    Block {
-      brace_token: token::Brace {
-        span: Span::mixed_site(),
-      },
-      stmts: lt_stmts,
+#[proc_macro_attribute]
+pub fn ct_public_inputs(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let public = parse_macro_input!(attr with analysis::parse_public_inputs);
+  let mut item: ItemFn = parse_macro_input!(item);
+
+  /* This expands before the checking attribute below it, which then only needs to find the
+   * `#[secret]` arguments. */
+  let is_checked = item.attrs.iter().any(|attr| {
+    attr.path.segments.last().map_or(false, |segment| {
+      analysis::CHECKING_ATTRS
+        .iter()
+        .any(|name| segment.ident == name)
+    })
+  });
+  if !is_checked {
+    return Code::MisplacedAttribute
+      .error_at(
+        Span::call_site(),
+        "#[ct_public_inputs] must be followed by #[ct_branchless], #[deny_secret_branches], \
+       #[ct_loop_bound], or #[ct_equalize]",
+      )
+      .to_compile_error()
+      .into();
+  }
+  if let Err(error) = analysis::mark_secret_inputs(&mut item.sig, &public) {
+    return error.to_compile_error().into();
+  }
+
+  quote!(#item).into()
+}
+
+/* The names of the derive macros exported by this crate. */
+const DERIVES: &[&str] = &[
+  "ConstantTimeEq",
+  "ConstEq",
+  "ConstHash",
+  "RedactedDebug",
+  "Secret",
+  "CtNew",
+  "CtFromBytes",
+  "CtToBytes",
+  #[cfg(feature = "serde")]
+  "CtDeserialize",
+  "ConstantTimeGreater",
+  "ConstantTimeLess",
+  "ConstantTimeOrd",
+  "ConstPartialOrd",
+  "ConstOrd",
+  "ConstNeg",
+  "CtMinMax",
+  "CtContains",
+  "CtLookup",
+  "CtSort",
+  "CtTest",
+  "CtVerify",
+];
+
+/* Add `derive` to every struct within `items`, including those in nested inline modules. */
+fn derive_structs(items: &mut [Item], derive: &Attribute) {
+  for item in items {
+    match item {
+      Item::Struct(ItemStruct { attrs, .. }) => attrs.insert(0, derive.clone()),
+      Item::Mod(ItemMod {
+        content: Some((_, items)),
+        ..
+      }) => derive_structs(items, derive),
+      _ => (),
     }
+  }
+}
+
+/// Derive the same set of traits for every struct in an inline module.
+///
+/// Each struct in the module, or in any inline module nested within it, has
+/// `#[derive(...)]` applied with the given derives. Derives from this crate may be named directly,
+/// since they are referred to by their full path within the module. Any other derive is used as
+/// written, and must be in scope within the module. Fields and structs can still be annotated with
+/// `#[ct(...)]` options as usual.
+///
+///```
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::constant_time_module;
+///
+/// #[constant_time_module(ConstantTimeEq, RedactedDebug)]
+/// mod keys {
+///   pub struct SigningKey(pub u64);
+///
+///   pub struct Session {
+///     #[ct(debug_public)]
+///     pub id: u32,
+///     pub secret: u64,
+///   }
+/// }
+///
+/// assert_eq!(1, keys::SigningKey(3).ct_eq(&keys::SigningKey(3)).unwrap_u8());
+/// let session = keys::Session { id: 1, secret: 2 };
+/// assert_eq!(format!("{:?}", session), "Session { id: 1, secret: [REDACTED] }");
+///```
+#[proc_macro_attribute]
+pub fn constant_time_module(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let derives =
+    parse_macro_input!(attr with punctuated::Punctuated::<Path, Token![,]>::parse_terminated);
+  let mut item: ItemMod = parse_macro_input!(item);
+
+  let derives = derives.into_iter().map(|path| match path.get_ident() {
+    Some(ident) if DERIVES.iter().any(|name| ident == name) => quote!(::subtle_derive::#ident),
+    _ => quote!(#path),
+  });
+  let derive: Attribute = parse_quote!(#[derive(#(#derives),*)]);
+  match &mut item.content {
+    Some((_, items)) => derive_structs(items, &derive),
+    None => {
+      return Code::UnsupportedItem
+        .error(
+          &item,
+          "#[constant_time_module] can only be applied to an inline module",
+        )
+        .to_compile_error()
+        .into();
+    },
+  }
+
+  quote!(#item).into()
+}
+
+/// Generate a constant-time accessor for a constant table.
+///
+/// Applied to `const NAME: [T; N]`, this generates `fn name_ct_get(index: &usize) -> T`, with the
+/// same visibility as the constant. Like the `ct_lookup()` method generated by
+/// [`CtLookup`](derive@CtLookup), the accessor reads every entry and conditionally assigns the one
+/// at `index` with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html),
+/// so precomputed tables can be indexed by secret values. `T` must also implement [`Default`],
+/// which is returned if `index` is out of bounds.
+///
+///```
+/// use subtle_derive::ct_table;
+///
+/// #[ct_table]
+/// const SBOX: [u8; 4] = [0x6, 0x4, 0xc, 0x5];
+///
+/// assert_eq!(sbox_ct_get(&2), 0xc);
+/// assert_eq!(sbox_ct_get(&4), 0);
+///```
+#[proc_macro_attribute]
+pub fn ct_table(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let item: ItemConst = parse_macro_input!(item);
+  let ItemConst { vis, ident, ty, .. } = &item;
+  let elem = match &**ty {
+    Type::Array(TypeArray { elem, .. }) => elem,
+    ty => {
+      return Code::UnsupportedItem
+        .error(ty, "#[ct_table] requires a constant of array type `[T; N]`")
+        .to_compile_error()
+        .into();
+    },
   };
+  let getter = format_ident!(
+    "{}_ct_get",
+    ident.unraw().to_string().to_lowercase(),
+    span = ident.span()
+  );
+  let doc = format!(
+    "Select `{}[*index]` while touching every entry, or the default value if `index` is out of \
+     bounds.",
+    ident.unraw()
+  );
 
-  /* Insert the ct_lt() block into the quoted trait method. */
+  let lints = allowed_lints();
   let output = quote! {
-    impl ::subtle::ConstantTimeLess for #ident {
-      #[inline]
-      fn ct_lt(&self, other: &Self) -> ::subtle::Choice {
-        use ::subtle::ConstantTimeLess;
-        #lt_block
+    #item
+
+    #[doc = #doc]
+    #[must_use]
+    #lints
+    #vis fn #getter(index: &usize) -> #elem {
+      let mut ret: #elem = ::core::default::Default::default();
+      for (i, entry) in ::core::iter::Iterator::enumerate(#ident.iter()) {
+        let is_match = ::subtle::ConstantTimeEq::ct_eq(&i, index);
+        ::subtle::ConditionallySelectable::conditional_assign(&mut ret, entry, is_match);
       }
+      ret
     }
   };
 
   output.into()
 }
 
-/// Implement [`PartialOrd`] given a [`subtle::ConstantTimePartialOrd`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimePartialOrd.html) implementation.
+/// Evaluate both arms of each `if` on a sensitive condition in a function.
+///
+/// Some branches can't be avoided, such as returning an error when verification fails, but should
+/// still take the same time either way. Arguments marked `#[secret]` are considered sensitive,
+/// along with any variable computed from them, as with [`macro@ct_branchless`]. Each
+/// `if cond { a } else { b }` on a sensitive condition is rewritten to evaluate `cond`, `a`, and
+/// `b` in that order, passing each arm's value through [`core::hint::black_box()`] so neither
+/// can be optimized out, and only then selecting one of the values with an `if`. An `if` without
+/// an `else` evaluates to `()` in the other arm.
+///
+/// Since both arms are always evaluated, they must not have side effects which should only happen
+/// on one path, and may not `return`, `break`, `continue`, or use `?`. Unlike
+/// [`macro@ct_branchless`], the arms may have any type, and the condition may be any `bool`.
 ///
 ///```
-/// use core::cmp::Ordering;
-/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd};
+/// use subtle::ConstantTimeEq;
+/// use subtle_derive::ct_equalize;
 ///
-/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd)]
-/// pub struct S(pub u8);
+/// fn encode_error(code: u8) -> [u8; 4] {
+///   [0xff, code, 0, 0]
+/// }
 ///
-/// assert!(S(0) == S(0));
-/// assert!(S(0).partial_cmp(&S(0)) == Some(Ordering::Equal));
-/// assert!(S(0).partial_cmp(&S(1)) == Some(Ordering::Less));
+/// fn encode_payload(payload: u32) -> [u8; 4] {
+///   payload.to_be_bytes()
+/// }
+///
+/// #[ct_equalize]
+/// fn respond(#[secret] tag: u32, expected: u32, payload: u32) -> [u8; 4] {
+///   let is_valid: bool = tag.ct_eq(&expected).into();
+///   if is_valid {
+///     encode_payload(payload)
+///   } else {
+///     encode_error(1)
+///   }
+/// }
+///
+/// assert_eq!(respond(3, 3, 0x01020304), [1, 2, 3, 4]);
+/// assert_eq!(respond(3, 4, 0x01020304), [0xff, 1, 0, 0]);
 ///```
-#[proc_macro_derive(ConstPartialOrd)]
-pub fn derive_partial_ord(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, .. } = parse_macro_input!(input);
+#[proc_macro_attribute]
+pub fn ct_equalize(_attr: TokenStream, item: TokenStream) -> TokenStream {
+  let mut item: ItemFn = parse_macro_input!(item);
+  let errors = equalize::rewrite_fn(&mut item);
 
   let output = quote! {
-    impl PartialOrd for #ident {
-      fn partial_cmp(&self, other: &Self) -> Option<::core::cmp::Ordering> {
-        use ::subtle::ConstantTimePartialOrd;
-        self.ct_partial_cmp(other).into()
-      }
-    }
+    #item
+    #errors
   };
 
   output.into()
 }
 
-/// Implement [`Ord`] given a [`subtle::ConstantTimeOrd`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeOrd.html) implementation.
+/// A single `(from, event) => to` transition of a `#[ct_fsm(...)]` table, where `None` matches
+/// any state or event.
+struct Transition {
+  from: Option<Ident>,
+  event: Option<Ident>,
+  to: Ident,
+}
+
+impl Parse for Transition {
+  fn parse(input: ParseStream) -> Result<Self> {
+    fn parse_pattern(input: ParseStream) -> Result<Option<Ident>> {
+      if input.peek(Token![_]) {
+        input.parse::<Token![_]>()?;
+        Ok(None)
+      } else {
+        input.parse().map(Some)
+      }
+    }
+
+    let content;
+    parenthesized!(content in input);
+    let from = parse_pattern(&content)?;
+    content.parse::<Token![,]>()?;
+    let event = parse_pattern(&content)?;
+    input.parse::<Token![=>]>()?;
+    let to = input.parse()?;
+    Ok(Self { from, event, to })
+  }
+}
+
+/// Arguments to `#[ct_fsm(...)]`.
+struct FsmArgs {
+  event: Path,
+  transitions: Vec<Transition>,
+}
+
+impl Parse for FsmArgs {
+  fn parse(input: ParseStream) -> Result<Self> {
+    let key: Ident = input.parse()?;
+    if key != "event" {
+      return Err(Code::InvalidMacroInput.error(key, "expected `event = EventType`"));
+    }
+    input.parse::<Token![=]>()?;
+    let event = input.parse()?;
+    let mut transitions = Vec::new();
+    while !input.is_empty() {
+      input.parse::<Token![,]>()?;
+      if input.is_empty() {
+        break;
+      }
+      transitions.push(input.parse()?);
+    }
+    Ok(Self { event, transitions })
+  }
+}
+
+/// Generate a branch-free state machine over an enum of states.
+///
+/// Applied to a fieldless `#[repr(u8)]` enum of states, with the arguments
+/// `event = EventType, (From, Event) => To, ...`, this generates a method
+/// `fn step(self, event: EventType) -> Self`. `EventType` must also be a fieldless enum implementing
+/// [`Copy`], which is `#[repr(u8)]` or otherwise fits in a byte, so that its discriminants can be
+/// compared as `u8`s, and `_` matches any state or event. Rather than branching on the current state,
+/// `step()` compares the current state and event against every transition with
+/// [`subtle::ConstantTimeEq`](https://docs.rs/subtle/latest/subtle/trait.ConstantTimeEq.html), and
+/// selects the next state with
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html).
+/// As with `match`, the first matching transition applies, and if none match, the state is
+/// unchanged.
+///
+/// This also implements
+/// [`subtle::ConditionallySelectable`](https://docs.rs/subtle/latest/subtle/trait.ConditionallySelectable.html)
+/// for the enum, which therefore must also implement [`Copy`]. The implementation selects between
+/// discriminants, and converts the result back with an `unsafe` transmute, which is sound because
+/// the enum is `#[repr(u8)]` and the result is always one of its discriminants. A `#[cfg(test)]`
+/// module named after the enum, as `<Enum>_ct_fsm_test`, is also generated, which selects between
+/// every pair of states, so that the transmute can be checked with `cargo miri test`.
 ///
 ///```
-/// use subtle::ConstantTimeOrd;
-/// use subtle_derive::{ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd, ConstOrd};
+/// use subtle_derive::ct_fsm;
 ///
-/// #[derive(Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstEq, ConstPartialOrd, ConstOrd)]
-/// pub struct S(pub u8);
-/// impl ConstantTimeOrd for S {}
+/// #[derive(Clone, Copy)]
+/// enum Event { Start, Ack, Reset }
 ///
-/// assert!(S(0) == S(0));
-/// assert!(S(0) < S(1));
-/// assert!(S(0) <= S(1));
+/// #[ct_fsm(
+///   event = Event,
+///   (_, Reset) => Idle,
+///   (Idle, Start) => Handshake,
+///   (Handshake, Ack) => Open,
+/// )]
+/// #[derive(Debug, Clone, Copy, PartialEq)]
+/// #[repr(u8)]
+/// enum State { Idle, Handshake, Open }
+///
+/// let state = State::Idle.step(Event::Ack);
+/// assert_eq!(state, State::Idle);
+/// let state = state.step(Event::Start).step(Event::Ack);
+/// assert_eq!(state, State::Open);
+/// assert_eq!(state.step(Event::Reset), State::Idle);
 ///```
-#[proc_macro_derive(ConstOrd)]
-pub fn derive_ord(input: TokenStream) -> TokenStream {
-  let DeriveInput { ident, .. } = parse_macro_input!(input);
+///
+/// Events whose discriminants don't fit in a byte would be truncated, so they are an error:
+///
+///```compile_fail
+/// use subtle_derive::ct_fsm;
+///
+/// #[derive(Clone, Copy)]
+/// #[repr(u16)]
+/// enum Event { Start = 1, Stop = 257 }
+///
+/// #[ct_fsm(event = Event, (Idle, Start) => Running, (Running, Stop) => Idle)]
+/// #[derive(Clone, Copy)]
+/// #[repr(u8)]
+/// enum State { Idle, Running }
+///```
+#[proc_macro_attribute]
+pub fn ct_fsm(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let FsmArgs { event, transitions } = parse_macro_input!(attr);
+  let item: ItemEnum = parse_macro_input!(item);
+  let ident = &item.ident;
 
-  let output = quote! {
-    impl Ord for #ident {
-      fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
-        use ::subtle::ConstantTimeOrd;
-        self.ct_cmp(other)
+  let is_repr_u8 = item.attrs.iter().any(|attr| {
+    attr.path.is_ident("repr")
+      && attr
+        .parse_args::<Ident>()
+        .map_or(false, |repr| repr == "u8")
+  });
+  if !is_repr_u8 {
+    return Code::UnsupportedItem
+      .error(ident, "#[ct_fsm] requires the enum to be #[repr(u8)]")
+      .to_compile_error()
+      .into();
+  }
+  if let Some(variant) = item
+    .variants
+    .iter()
+    .find(|variant| !matches!(variant.fields, Fields::Unit))
+  {
+    return Code::UnsupportedItem
+      .error(variant, "#[ct_fsm] requires every variant to be fieldless")
+      .to_compile_error()
+      .into();
+  }
+
+  /* Assign transitions in reverse, so that the first matching transition is assigned last. */
+  let assignments = transitions.iter().rev().map(
+    |Transition {
+       from,
+       event: on,
+       to,
+     }| {
+      let from = from
+        .iter()
+        .map(|from| quote!(& ::subtle::ConstantTimeEq::ct_eq(&state, &(Self::#from as u8))));
+      let on = on
+        .iter()
+        .map(|on| quote!(& ::subtle::ConstantTimeEq::ct_eq(&event, &(#event::#on as u8))));
+      quote! {
+        let is_match = <::subtle::Choice as ::core::convert::From<u8>>::from(1u8) #(#from)* #(#on)*;
+        ::subtle::ConditionallySelectable::conditional_assign(&mut next, &Self::#to, is_match);
+      }
+    },
+  );
+
+  /* Events are compared as `event as u8`, which only tells them apart if their discriminants fit
+   * in a byte, as they do exactly when the enum does. */
+  let event_error = Code::UnsupportedItem
+    .message("#[ct_fsm] requires the event enum to be #[repr(u8)], or otherwise to fit in a byte");
+  let derived = derived_attrs();
+  let lints = allowed_lints();
+  let impls = anonymous_scope(quote! {
+    const _: () = ::core::assert!(::core::mem::size_of::<#event>() <= 1, #event_error);
+
+    #derived
+    impl ::subtle::ConditionallySelectable for #ident {
+      fn conditional_select(a: &Self, b: &Self, choice: ::subtle::Choice) -> Self {
+        let selected =
+          <u8 as ::subtle::ConditionallySelectable>::conditional_select(
+            &(*a as u8),
+            &(*b as u8),
+            choice,
+          );
+        /* SAFETY: `Self` is `#[repr(u8)]`, and `selected` is the discriminant of `a` or `b`. */
+        unsafe { ::core::mem::transmute::<u8, Self>(selected) }
+      }
+    }
+
+    #lints
+    impl #ident {
+      /// Compute the next state after `event`, evaluating every transition.
+      #[allow(unused_mut, unused_variables)]
+      #[must_use]
+      pub fn step(self, event: #event) -> Self {
+        let state = self as u8;
+        let event = event as u8;
+        let mut next = self;
+        #(#assignments)*
+        next
       }
     }
+  });
+
+  /* Exercise the transmute for every pair of states, so that Miri can check each of them. Like the
+   * tests from CtTest, these must stay outside the anonymous scope to be run. The name keeps the
+   * case of the enum, so that enums whose names only differ in case get different modules. */
+  let module = format_ident!("{}_ct_fsm_test", ident.unraw(), span = ident.span());
+  let states = item.variants.iter().map(|variant| &variant.ident);
+  let tests = quote! {
+    #[cfg(test)]
+    #[allow(non_snake_case)]
+    #lints
+    mod #module {
+      #[test]
+      fn conditional_select_every_state() {
+        let states = [#(super::#ident::#states),*];
+        for a in &states {
+          for b in &states {
+            for choice in [0u8, 1u8] {
+              let selected = <super::#ident as ::subtle::ConditionallySelectable>::conditional_select(
+                a,
+                b,
+                <::subtle::Choice as ::core::convert::From<u8>>::from(choice),
+              );
+              let expected = if choice == 1 { b } else { a };
+              ::core::assert_eq!(selected as u8, *expected as u8);
+            }
+          }
+        }
+      }
+    }
+  };
+
+  let output = quote! {
+    #item
+
+    #impls
+
+    #tests
   };
 
   output.into()
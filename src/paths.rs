@@ -0,0 +1,68 @@
+//! Selection of the crate which generated code refers to as `::subtle`.
+
+use proc_macro2::{Group, Spacing, TokenStream, TokenTree};
+use quote::quote;
+use syn::*;
+
+/* The keywords which can come directly before the start of an absolute path, as in
+ * `impl ::subtle::ConstantTimeEq`. `crate`, `self`, `super` and `Self` are missing, since they
+ * start relative paths instead. */
+const KEYWORDS: &[&str] = &[
+  "as", "break", "const", "continue", "dyn", "else", "enum", "extern", "fn", "for", "if", "impl",
+  "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+  "trait", "type", "unsafe", "use", "where", "while",
+];
+
+/* Get the path to the crate providing the subtle traits: `krate` if the type being derived gave
+ * one with `#[ct(crate = "...")]`, or else `::subtle`. */
+pub(crate) fn subtle_path(krate: Option<&Path>) -> Path {
+  match krate {
+    Some(krate) => krate.clone(),
+    None => parse_quote!(::subtle),
+  }
+}
+
+/* Whether a `::` following `prev` starts an absolute path, rather than continuing one. */
+fn starts_path(prev: Option<&TokenTree>) -> bool {
+  match prev {
+    Some(TokenTree::Ident(ident)) => KEYWORDS.iter().any(|keyword| ident == keyword),
+    Some(TokenTree::Punct(punct)) => punct.spacing() == Spacing::Alone,
+    _ => true,
+  }
+}
+
+/* Replace every absolute path starting with `::subtle` within `tokens` with one starting with
+ * `subtle` instead. */
+pub(crate) fn rewrite_subtle_paths(tokens: TokenStream, subtle: &Path) -> TokenStream {
+  let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+  let mut ret: Vec<TokenTree> = Vec::with_capacity(tokens.len());
+  let mut i = 0;
+  while i < tokens.len() {
+    match (&tokens[i], tokens.get(i + 1), tokens.get(i + 2)) {
+      (TokenTree::Group(group), _, _) => {
+        let mut rewritten = Group::new(
+          group.delimiter(),
+          rewrite_subtle_paths(group.stream(), subtle),
+        );
+        rewritten.set_span(group.span());
+        ret.push(rewritten.into());
+        i += 1;
+      },
+      (TokenTree::Punct(first), Some(TokenTree::Punct(second)), Some(TokenTree::Ident(ident)))
+        if first.as_char() == ':'
+          && first.spacing() == Spacing::Joint
+          && second.as_char() == ':'
+          && ident == "subtle"
+          && starts_path(ret.last()) =>
+      {
+        ret.extend(quote!(#subtle));
+        i += 3;
+      },
+      (token, _, _) => {
+        ret.push(token.clone());
+        i += 1;
+      },
+    }
+  }
+  ret.into_iter().collect()
+}
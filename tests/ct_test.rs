@@ -0,0 +1,93 @@
+//! Compile and run the tests generated by `CtTest`, which only exist under `cfg(test)` and so are
+//! never built by the doctests.
+
+use proptest::{
+  arbitrary::{any, Arbitrary},
+  strategy::{BoxedStrategy, Strategy},
+};
+use subtle_derive::{
+  ConstEq, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd, CtTest,
+};
+
+#[derive(
+  Debug, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd, ConstEq, CtTest,
+)]
+#[ct(test(partial_eq, ord, cmp))]
+struct Key {
+  id: u32,
+  bytes: [u8; 4],
+}
+
+impl Arbitrary for Key {
+  type Parameters = ();
+  type Strategy = BoxedStrategy<Self>;
+
+  fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+    (any::<u32>(), any::<[u8; 4]>())
+      .prop_map(|(id, bytes)| Key { id, bytes })
+      .boxed()
+  }
+}
+
+/* Only the timing test is generated, so this needs no `Arbitrary` impl. The test is ignored, so
+ * `cargo test` only builds it, and it runs with `cargo test --release -- --ignored ct_eq_timing`. */
+#[derive(ConstantTimeEq, CtTest)]
+#[ct(test(timing(equal = "equal_tokens", different = "different_tokens")))]
+struct Token {
+  id: u32,
+  bytes: [u8; 4],
+}
+
+fn equal_tokens() -> (Token, Token) {
+  (
+    Token {
+      id: 1,
+      bytes: [0; 4],
+    },
+    Token {
+      id: 1,
+      bytes: [0; 4],
+    },
+  )
+}
+
+fn different_tokens() -> (Token, Token) {
+  (
+    Token {
+      id: 1,
+      bytes: [0; 4],
+    },
+    Token {
+      id: !1,
+      bytes: [!0; 4],
+    },
+  )
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+  Low,
+  High,
+}
+
+impl subtle::ConstantTimeEq for Level {
+  fn ct_eq(&self, other: &Self) -> subtle::Choice {
+    subtle::ConstantTimeEq::ct_eq(&(*self as u8), &(*other as u8))
+  }
+}
+
+#[derive(ConstantTimeEq, CtTest)]
+#[ct(test(exhaustive))]
+struct Flags {
+  mask: u8,
+  on: bool,
+  #[ct(domain = "[Level::Low, Level::High]")]
+  level: Level,
+}
+
+#[derive(ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess, ConstantTimeOrd, CtTest)]
+#[ct(test(exhaustive, ord, cmp))]
+struct Offset {
+  delta: i8,
+  carry: bool,
+}
@@ -0,0 +1,73 @@
+//! Compile the `dudect` benchmarks generated by `#[ct_verify]` and `CtVerify`, which are normally
+//! only built with `--cfg ct_verify`, by building them under `cfg(test)` instead.
+
+use core::any::type_name_of_val;
+use dudect_bencher::{rand::RngCore, BenchRng, CtRunner};
+use subtle::{Choice, ConstantTimeEq};
+use subtle_derive::{ct_verify, ConstantTimeEq, CtVerify};
+
+#[ct_verify(left = "equal_bytes", right = "random_bytes", samples = 100, cfg(test))]
+fn bytes_match(a: &[u8; 32], b: [u8; 32]) -> Choice { a.ct_eq(&b) }
+
+fn equal_bytes(_rng: &mut BenchRng) -> ([u8; 32], [u8; 32]) { ([0; 32], [0; 32]) }
+
+fn random_bytes(rng: &mut BenchRng) -> ([u8; 32], [u8; 32]) {
+  let mut b = [0; 32];
+  rng.fill_bytes(&mut b);
+  ([0; 32], b)
+}
+
+#[derive(ConstantTimeEq, CtVerify)]
+#[ct(verify(left = "equal_keys", right = "random_keys", samples = 100, cfg(test)))]
+struct Key {
+  id: u32,
+  bytes: [u8; 32],
+}
+
+fn equal_keys(_rng: &mut BenchRng) -> (Key, Key) {
+  (
+    Key {
+      id: 0,
+      bytes: [0; 32],
+    },
+    Key {
+      id: 0,
+      bytes: [0; 32],
+    },
+  )
+}
+
+fn random_keys(rng: &mut BenchRng) -> (Key, Key) {
+  let mut bytes = [0; 32];
+  rng.fill_bytes(&mut bytes);
+  (
+    Key {
+      id: 0,
+      bytes: [0; 32],
+    },
+    Key {
+      id: rng.next_u32(),
+      bytes,
+    },
+  )
+}
+
+/* The benchmarks are run by the harness of `ctbench_main!`, so this checks that they have the
+ * signature it takes, and are named after the function and the type. */
+#[test]
+fn benches_are_named_for_ctbench_main() {
+  let _: [fn(&mut CtRunner, &mut BenchRng); 2] = [bytes_match_ct_verify, key_ct_verify];
+  assert_eq!(
+    [
+      type_name_of_val(&bytes_match_ct_verify),
+      type_name_of_val(&key_ct_verify),
+    ],
+    [
+      "ct_verify::bytes_match_ct_verify",
+      "ct_verify::key_ct_verify"
+    ],
+  );
+  /* The annotated function itself is left as it was. */
+  assert_eq!(1, bytes_match(&[1; 32], [1; 32]).unwrap_u8());
+  assert_eq!(0, bytes_match(&[1; 32], [2; 32]).unwrap_u8());
+}
@@ -0,0 +1,40 @@
+//! Build the proof harness generated by `#[ct(kani)]`, which only exists under `cfg(kani)`. Plain
+//! `cargo test` checks that the type still compares as usual without it, and `cargo kani --tests`
+//! compiles and verifies the harness itself.
+
+use subtle::ConstantTimeEq;
+use subtle_derive::ConstantTimeEq;
+
+#[derive(ConstantTimeEq)]
+#[cfg_attr(kani, derive(kani::Arbitrary))]
+#[ct(kani)]
+struct Pin {
+  digits: [u8; 4],
+  attempts: u8,
+  #[ct(float_bits)]
+  weight: f32,
+}
+
+#[test]
+fn compares_without_kani() {
+  let pin = Pin {
+    digits: [1, 2, 3, 4],
+    attempts: 0,
+    weight: 0.5,
+  };
+  assert!(bool::from(pin.ct_eq(&Pin {
+    digits: [1, 2, 3, 4],
+    attempts: 0,
+    weight: 0.5,
+  })));
+  assert!(!bool::from(pin.ct_eq(&Pin {
+    digits: [1, 2, 3, 5],
+    attempts: 0,
+    weight: 0.5,
+  })));
+  assert!(!bool::from(pin.ct_eq(&Pin {
+    digits: [1, 2, 3, 4],
+    attempts: 0,
+    weight: -0.5,
+  })));
+}